@@ -50,6 +50,7 @@ pub async fn download_http(
                 app = app_name,
                 bar = "{wide_msg}[{bar:60.green/blue}] {percent:>3}% {total_bytes:>10}"
             ))
+            .unwrap()
             .progress_chars("#>-"),
     );
 
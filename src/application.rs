@@ -1,13 +1,14 @@
 use super::{
     statics::{LOCAL_DIR, MANI_FILE},
-    Dependency, Metadata, Security,
+    Constraint, Dependency, Hooks, Metadata, Security,
 };
 
 use serde::{Deserialize, Serialize};
 use std::{
+    convert::TryFrom,
     fs::File,
     io::{Error, ErrorKind},
-    path::PathBuf,
+    path::{Component, Path, PathBuf},
 };
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
@@ -15,14 +16,26 @@ pub struct Application {
     pub metadata: Metadata,
     pub security: Option<Security>,
     pub dependencies: Option<Dependency>,
-    pub files: Vec<String>,
+    pub backup: Option<Vec<String>>,
+    pub hooks: Option<Hooks>,
+    pub files: Vec<FileEntry>,
+    // Size of the .app in bytes, recorded in repo databases by bin-repo.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    // Hex sha256 of the .app, checked before a cached copy is reused.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
 }
 
 impl Application {
+    /// `name-[epoch:]version-release-arch`, the file name of the package
+    /// without its suffix.
     pub fn archive_name(&self) -> String {
         format!(
-            "{}-{}-{}-{}",
-            self.metadata.name, self.metadata.version, self.metadata.release, "x86_64"
+            "{}-{}-{}",
+            self.metadata.name,
+            self.metadata.full_version(),
+            "x86_64"
         )
     }
     pub fn write(&self) -> Result<(), std::io::Error> {
@@ -37,6 +50,108 @@ impl Application {
         }
     }
 
+    pub fn file_paths(&self) -> Vec<String> {
+        self.files.iter().map(|f| f.path.clone()).collect()
+    }
+
+    /// The entry of `path`, relative to ROOT_DIR.
+    pub fn file_entry(&self, path: &str) -> Option<&FileEntry> {
+        self.files.iter().find(|f| f.path == path)
+    }
+
+    pub fn run_dependencies(&self) -> Vec<String> {
+        match &self.dependencies {
+            Some(deps) => deps.run_dependencies.clone().unwrap_or_default(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn backup_files(&self) -> Vec<String> {
+        self.backup
+            .clone()
+            .unwrap_or_default()
+            .iter()
+            .map(|p| p.trim_start_matches('/').to_string())
+            .collect()
+    }
+
+    pub fn provides(&self) -> Vec<String> {
+        match &self.dependencies {
+            Some(deps) => deps.provides.clone().unwrap_or_default(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn conflicts(&self) -> Vec<String> {
+        match &self.dependencies {
+            Some(deps) => deps.conflicts.clone().unwrap_or_default(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn replaces(&self) -> Vec<String> {
+        match &self.dependencies {
+            Some(deps) => deps.replaces.clone().unwrap_or_default(),
+            None => Vec::new(),
+        }
+    }
+
+    // The version this package offers for `name`, either its own or the one
+    // given in a `provides` entry such as `sh=5.1`.
+    pub fn provided_version(&self, name: &str) -> Option<String> {
+        if self.metadata.name == name {
+            return Some(self.metadata.full_version());
+        }
+        self.provides()
+            .iter()
+            .find_map(|p| match Constraint::parse(p) {
+                Ok(c) if c.name == name && c.version.is_empty() => {
+                    Some(self.metadata.full_version())
+                }
+                Ok(c) if c.name == name => Some(c.version),
+                _ => None,
+            })
+    }
+
+    pub fn provides_name(&self, name: &str) -> bool {
+        self.provided_version(name).is_some()
+    }
+
+    pub fn conflicts_with(&self, other: &Application) -> bool {
+        if self.metadata.name == other.metadata.name {
+            return false;
+        }
+        let declared = |a: &Application, b: &Application| {
+            a.conflicts().iter().any(|c| match Constraint::parse(c) {
+                Ok(c) => match b.provided_version(&c.name) {
+                    Some(v) => c.satisfied_by(&v),
+                    None => false,
+                },
+                Err(_) => false,
+            })
+        };
+        declared(self, other) || declared(other, self)
+    }
+
+    /// Packages installed before reasons were recorded count as explicit.
+    pub fn install_reason(name: &str) -> InstallReason {
+        match std::fs::read_to_string(LOCAL_DIR.join(name).join("reason")) {
+            Ok(text) if text.trim() == "dependency" => InstallReason::Dependency,
+            _ => InstallReason::Explicit,
+        }
+    }
+
+    pub fn set_install_reason(name: &str, reason: InstallReason) -> Result<(), Error> {
+        let dir = LOCAL_DIR.join(name);
+        if !dir.join("manifest.yml").exists() {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("{} is not installed", name),
+            ));
+        }
+        std::fs::write(dir.join("reason"), reason.to_string())
+    }
+
     pub fn is_installed(name: &str) -> Option<Application> {
         let path: PathBuf = PathBuf::from(LOCAL_DIR.join(&format!("{}/manifest.yml", name)));
         if path.exists() {
@@ -49,3 +164,202 @@ impl Application {
         }
     }
 }
+
+// Why a package is installed. Dependencies nothing needs anymore are
+// removed by `pi autoremove`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InstallReason {
+    Explicit,
+    Dependency,
+}
+
+impl std::fmt::Display for InstallReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InstallReason::Explicit => write!(f, "explicit"),
+            InstallReason::Dependency => write!(f, "dependency"),
+        }
+    }
+}
+
+// One entry of `files`: every file, directory and link of the payload with
+// the ownership and mode it is installed with. Manifests of older packages
+// list regular files only, as "<path> <sha256>" lines, and installed ones
+// add the octal mode: "<path> <sha256> <mode>". Those still read.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(try_from = "RawEntry")]
+pub struct FileEntry {
+    pub path: String,
+    #[serde(rename = "type")]
+    pub kind: FileKind,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub sha256: String,
+    #[serde(default, with = "octal", skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uid: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gid: Option<u32>,
+    // what a symlink points to, or the path a hardlink shares its data with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    // set on directories that did not exist before the package was
+    // installed, only those are removed with it
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub created: bool,
+}
+
+fn is_false(b: &bool) -> bool {
+    !*b
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileKind {
+    File,
+    Dir,
+    Symlink,
+    Hardlink,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawEntry {
+    Line(String),
+    Full {
+        path: String,
+        #[serde(rename = "type")]
+        kind: FileKind,
+        #[serde(default)]
+        sha256: String,
+        #[serde(default, with = "octal")]
+        mode: Option<u32>,
+        #[serde(default)]
+        uid: Option<u32>,
+        #[serde(default)]
+        gid: Option<u32>,
+        #[serde(default)]
+        target: Option<String>,
+        #[serde(default)]
+        created: bool,
+    },
+}
+
+// Paths and hardlink targets are joined to the staging area and ROOT_DIR,
+// so they have to stay below them.
+impl TryFrom<RawEntry> for FileEntry {
+    type Error = String;
+
+    fn try_from(raw: RawEntry) -> Result<Self, String> {
+        let entry = match raw {
+            RawEntry::Line(line) => Self::parse(&line),
+            RawEntry::Full {
+                path,
+                kind,
+                sha256,
+                mode,
+                uid,
+                gid,
+                target,
+                created,
+            } => Self {
+                path,
+                kind,
+                sha256,
+                mode,
+                uid,
+                gid,
+                target,
+                created,
+            },
+        };
+        if !is_relative(&entry.path) {
+            return Err(format!(
+                "{}: not a relative path in the package",
+                entry.path
+            ));
+        }
+        match (&entry.kind, &entry.target) {
+            (FileKind::Hardlink, Some(target)) if !is_relative(target) => Err(format!(
+                "{}: hardlink to {} outside the package",
+                entry.path, target
+            )),
+            (FileKind::Hardlink, None) | (FileKind::Symlink, None) => {
+                Err(format!("{}: link without a target", entry.path))
+            }
+            _ => Ok(entry),
+        }
+    }
+}
+
+// Only plain names, no `/`, `.` or `..` components.
+fn is_relative(path: &str) -> bool {
+    !path.is_empty()
+        && Path::new(path)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+}
+
+impl FileEntry {
+    pub fn new(path: String, kind: FileKind) -> Self {
+        Self {
+            path,
+            kind,
+            sha256: String::new(),
+            mode: None,
+            uid: None,
+            gid: None,
+            target: None,
+            created: false,
+        }
+    }
+
+    /// Read an old style "<path> <sha256> [<mode>]" line.
+    pub fn parse(entry: &str) -> Self {
+        let fields: Vec<&str> = entry.rsplitn(3, ' ').collect();
+        let is_hash = |s: &str| s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit());
+        let mut file = Self::new(String::new(), FileKind::File);
+        match fields.as_slice() {
+            [mode, sha, path] if is_hash(sha) => match u32::from_str_radix(mode, 8) {
+                Ok(m) => {
+                    file.path = path.to_string();
+                    file.sha256 = sha.to_string();
+                    file.mode = Some(m);
+                }
+                Err(_) => {
+                    file.path = format!("{} {}", path, sha);
+                    file.sha256 = mode.to_string();
+                }
+            },
+            [sha, rest @ ..] => {
+                let mut path: Vec<&str> = rest.to_vec();
+                path.reverse();
+                file.path = path.join(" ");
+                file.sha256 = sha.to_string();
+            }
+            [] => {}
+        }
+        file
+    }
+}
+
+// Modes are written in octal, "4755" reads better than 2541.
+mod octal {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(mode: &Option<u32>, s: S) -> Result<S::Ok, S::Error> {
+        match mode {
+            Some(m) => s.serialize_str(&format!("{:04o}", m)),
+            None => s.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<u32>, D::Error> {
+        match Option::<String>::deserialize(d)? {
+            Some(m) => u32::from_str_radix(&m, 8)
+                .map(Some)
+                .map_err(D::Error::custom),
+            None => Ok(None),
+        }
+    }
+}
@@ -1,16 +1,17 @@
-use pi::statics::SUFFIX_APP;
-use pi::utils::decompress_zstd;
-use pi::{Application, BinRepo};
+use pi::check::hash_file;
+use pi::signature::sig_path;
+use pi::utils::read_manifest;
+use pi::{Application, BinRepo, Lock, SecretKey};
 use serde_yaml::{from_reader, to_writer};
+use std::os::unix::fs::PermissionsExt;
 use std::time::SystemTime;
 use std::{
     env,
-    fs::{copy, create_dir_all, File},
-    io::{Error, ErrorKind, Read},
+    fs::{copy, create_dir_all, remove_file, rename, set_permissions, write, File, Permissions},
+    io::{Error, ErrorKind},
     path::{Path, PathBuf},
     result::Result,
 };
-use tar::Archive;
 
 fn main() {
     let args: Vec<String> = env::args_os()
@@ -59,10 +60,58 @@ fn main() {
                 eprintln!("Repo name is require")
             }
         }
+        "k" | "keygen" | "-k" | "--keygen" => {
+            if let Some(name) = repo {
+                keygen(name).unwrap();
+            } else {
+                eprintln!("Key name is require")
+            }
+        }
+        "s" | "sign" | "-s" | "--sign" => {
+            if let Some(key) = repo {
+                if let Some(files) = packages {
+                    sign(key, files)
+                } else {
+                    println!("No files was given");
+                }
+            } else {
+                eprintln!("Secret key is require")
+            }
+        }
         _ => help(),
     }
 }
 
+fn keygen(name: &str) -> Result<(), Error> {
+    let key_file = PathBuf::from(format!("{}.key", name));
+    if key_file.exists() {
+        return Err(Error::new(
+            ErrorKind::AlreadyExists,
+            format!("{} already exists", key_file.display()),
+        ));
+    }
+    let key = SecretKey::generate();
+    write(&key_file, key.to_text())?;
+    set_permissions(&key_file, Permissions::from_mode(0o600))?;
+    write(format!("{}.pub", name), key.public().to_text())?;
+    println!("{} {}", key.public().id_hex(), key_file.display());
+    Ok(())
+}
+
+fn sign(key_file: &str, files: Vec<String>) {
+    let key = match SecretKey::from_file(Path::new(key_file)) {
+        Ok(k) => k,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    for file in files.iter() {
+        let sig = key.sign_file(Path::new(file)).unwrap();
+        println!("{}", sig.display());
+    }
+}
+
 fn create(path: &str) -> Result<(), Error> {
     let p = Path::new(path);
     if let Some(p) = p.parent() {
@@ -71,12 +120,7 @@ fn create(path: &str) -> Result<(), Error> {
         }
     }
 
-    let file = File::create(path)?;
-    let data = BinRepo::new();
-    match to_writer(file, &data) {
-        Ok(_) => Ok(()),
-        Err(e) => Err(Error::new(ErrorKind::Other, e.to_string())),
-    }
+    update_db(path, &BinRepo::new())
 }
 
 fn update_db(path: &str, data: &BinRepo) -> Result<(), Error> {
@@ -87,11 +131,27 @@ fn update_db(path: &str, data: &BinRepo) -> Result<(), Error> {
         }
     }
 
-    let file = File::create(path)?;
-    // let data = BinRepo::default();
+    // written next to the old one and renamed over it, so the server and
+    // pi never read a half written database
+    let tmp = format!("{}.part", path);
+    let file = File::create(&tmp)?;
     match to_writer(file, data) {
-        Ok(_) => Ok(()),
-        Err(e) => Err(Error::new(ErrorKind::Other, e.to_string())),
+        Ok(_) => rename(&tmp, path),
+        Err(e) => {
+            remove_file(&tmp).ok();
+            Err(Error::other(e.to_string()))
+        }
+    }
+}
+
+// Keeps two runs from adding or removing packages at the same time.
+fn lock_db(path: &str) -> Lock {
+    match Lock::acquire(Path::new(&format!("{}.lck", path))) {
+        Ok(lock) => lock,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
     }
 }
 
@@ -99,47 +159,31 @@ fn opendb(path: &str) -> Result<BinRepo, Error> {
     let db_file = File::open(path)?;
     match from_reader(db_file) {
         Ok(db) => Ok(db),
-        Err(e) => Err(Error::new(ErrorKind::Other, e.to_string())),
+        Err(e) => Err(Error::other(e.to_string())),
     }
 }
 
 fn add(db_path: &str, pkg_files: Vec<PathBuf>) {
+    let _lock = lock_db(db_path);
     let mut db: BinRepo = opendb(db_path).unwrap();
 
     for pkg_file in pkg_files.iter() {
         let pkg_file_name = pkg_file.to_str().unwrap();
 
-        let file_name: String = pkg_file_name
-            .to_string()
-            .trim_end_matches(SUFFIX_APP.as_str())
-            .to_string();
-
-        decompress_zstd(pkg_file.to_str().unwrap()).unwrap();
-
-        let mut arch = Archive::new(File::open(&file_name).unwrap());
-        let mut manif = arch.entries().unwrap().skip_while(|entry| {
-            entry.as_ref().unwrap().path().unwrap().to_str().unwrap() != "manifest.yml"
-        });
-
         let db_file = PathBuf::from(db_path);
         let db_dir = db_file.parent().unwrap();
 
         copy(pkg_file_name, db_dir.join(pkg_file_name)).unwrap();
+        let sig_file = sig_path(pkg_file);
+        if sig_file.exists() {
+            copy(&sig_file, db_dir.join(&sig_file)).unwrap();
+        }
 
-        let mut buf: String = String::new();
-        manif
-            .nth(0)
-            .unwrap()
-            .unwrap()
-            .read_to_string(&mut buf)
-            .unwrap();
-        let data: Application = serde_yaml::from_str(&buf).unwrap();
-
-        db.applications
-            .entry(data.metadata.name.clone())
-            .or_insert(data);
+        let mut data: Application = read_manifest(pkg_file).unwrap();
+        data.size = Some(pkg_file.metadata().unwrap().len());
+        data.sha256 = Some(hash_file(pkg_file).unwrap());
 
-        std::fs::remove_file(file_name).unwrap();
+        db.insert(data);
     }
     let now = SystemTime::now();
     db.date = now;
@@ -147,15 +191,20 @@ fn add(db_path: &str, pkg_files: Vec<PathBuf>) {
 }
 
 fn remove(db_path: &str, pkg_files: Vec<PathBuf>) {
+    let _lock = lock_db(db_path);
     let mut db: BinRepo = opendb(db_path).unwrap();
     let db_file = PathBuf::from(db_path);
     let db_dir = db_file.parent().unwrap();
 
     if !pkg_files.is_empty() {
         for pkg in pkg_files.iter() {
-            if let Some((_, app)) = db.applications.remove_entry(pkg.to_str().unwrap()) {
+            for app in db.remove(pkg.to_str().unwrap()).iter() {
                 let file_name = format!("{}.app", app.archive_name());
-                std::fs::remove_file(db_dir.join(file_name)).unwrap();
+                std::fs::remove_file(db_dir.join(&file_name)).unwrap();
+                let sig_file = sig_path(&db_dir.join(&file_name));
+                if sig_file.exists() {
+                    std::fs::remove_file(sig_file).unwrap();
+                }
             }
         }
     }
@@ -173,7 +222,14 @@ bin-repo <operation> <repo_name> [packages]
 Operations:
     create <repo_name>              generation an empty repo with the given name.
     add <repo_name> [packages]      add the packages to that repo.
-    remove <repo_name> [package]    remove the packages to that repo.
+    remove <repo_name> [package]    remove the packages and all their versions from that repo.
+    keygen <name>                   generate <name>.key and <name>.pub for signing.
+    sign <name.key> [files]         write a detached <file>.sig for every file.
+
+Adding a new version of a package keeps the old one in the archive of the
+repo, for downgrades. Signatures of packages are copied along when they sit
+next to the package file. Sign the repo database again after every add or
+remove.
 "#
     );
 }
@@ -62,7 +62,8 @@ fn cfg_data() -> ServerConfig {
     cfg
 }
 
-async fn with_param(_req: HttpRequest, web::Path((name,)): web::Path<(String,)>) -> HttpResponse {
+async fn with_param(_req: HttpRequest, path: web::Path<(String,)>) -> HttpResponse {
+    let (name,) = path.into_inner();
     let cfg = cfg_data();
     let db_dir = PathBuf::from(&cfg.repo_root);
     let db_path = db_dir.join(&format!("{}/{}.db", &name, &name));
@@ -1,10 +1,10 @@
-use pi::{BuildFile, SourceRepo};
+use pi::{BuildFile, Lock, SourceRepo};
 use serde_yaml::{from_reader, to_writer};
 use std::time::SystemTime;
 use std::{
     env,
-    fs::{copy, create_dir_all, File},
-    io::{Error, ErrorKind},
+    fs::{copy, create_dir_all, remove_file, rename, File},
+    io::Error,
     path::{Path, PathBuf},
     result::Result,
 };
@@ -68,12 +68,7 @@ fn create(path: &str) -> Result<(), Error> {
         }
     }
 
-    let file = File::create(path)?;
-    let data = SourceRepo::new();
-    match to_writer(file, &data) {
-        Ok(_) => Ok(()),
-        Err(e) => Err(Error::new(ErrorKind::Other, e.to_string())),
-    }
+    update_db(path, &SourceRepo::new())
 }
 
 fn update_db(path: &str, data: &SourceRepo) -> Result<(), Error> {
@@ -84,11 +79,27 @@ fn update_db(path: &str, data: &SourceRepo) -> Result<(), Error> {
         }
     }
 
-    let file = File::create(path)?;
-    // let data = SourceRepo::default();
+    // written next to the old one and renamed over it, so the server and
+    // pi never read a half written database
+    let tmp = format!("{}.part", path);
+    let file = File::create(&tmp)?;
     match to_writer(file, data) {
-        Ok(_) => Ok(()),
-        Err(e) => Err(Error::new(ErrorKind::Other, e.to_string())),
+        Ok(_) => rename(&tmp, path),
+        Err(e) => {
+            remove_file(&tmp).ok();
+            Err(Error::other(e.to_string()))
+        }
+    }
+}
+
+// Keeps two runs from adding or removing packages at the same time.
+fn lock_db(path: &str) -> Lock {
+    match Lock::acquire(Path::new(&format!("{}.lck", path))) {
+        Ok(lock) => lock,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
     }
 }
 
@@ -96,11 +107,12 @@ fn opendb(path: &str) -> Result<SourceRepo, Error> {
     let db_file = File::open(path)?;
     match from_reader(db_file) {
         Ok(db) => Ok(db),
-        Err(e) => Err(Error::new(ErrorKind::Other, e.to_string())),
+        Err(e) => Err(Error::other(e.to_string())),
     }
 }
 
 fn add(db_path: &str, pkg_files: Vec<PathBuf>) {
+    let _lock = lock_db(db_path);
     let mut db: SourceRepo = opendb(db_path).unwrap();
 
     for pkg_file in pkg_files.iter() {
@@ -127,6 +139,7 @@ fn add(db_path: &str, pkg_files: Vec<PathBuf>) {
 }
 
 fn remove(db_path: &str, pkg_files: Vec<PathBuf>) {
+    let _lock = lock_db(db_path);
     let mut db: SourceRepo = opendb(db_path).unwrap();
 
     let db_file = PathBuf::from(db_path);
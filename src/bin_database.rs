@@ -1,28 +1,35 @@
 use super::Application;
-use crate::utils::{decompress_zstd, download_http, extract_archive};
-use crate::{Configuration, CACHE_DIR, LOCAL_DIR, ROOT_DIR, SUFFIX_APP, SYNC_DIR};
+use crate::cache::{cache_path, cached, check_file, CACHE_REPO};
+use crate::signature::{sig_path, verify_download, Keyring};
+use crate::sync::{sync_repo, SyncStatus};
+use crate::utils::{download_all, read_manifest, Download};
+use crate::{
+    dependency_names, installed, orphans, Configuration, Index, PkgVersion, Plan, Resolver, Step,
+    Transaction,
+};
 use colored::Colorize;
 use indicatif::ProgressBar;
-use semver::Version;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use serde_yaml;
-use solvent::DepGraph;
+use std::fs::File;
 use std::{collections::HashMap, path::PathBuf};
 use std::{fs::remove_file, time::SystemTime};
-use std::{fs::File, io::Read};
 use url::Url;
 
-use walkdir::WalkDir;
-
-#[derive(Clone, Debug, Deserialize, Serialize)]
+// The synced repos, by name.
+#[derive(Clone, Debug)]
 pub struct BinDatabase {
-    pub repos: HashMap<String, BinRepo>,
+    pub repos: HashMap<String, Index>,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct BinRepo {
     pub applications: HashMap<String, Application>,
     pub date: SystemTime,
+    // Older versions still on the server, oldest first, for downgrades.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub archive: HashMap<String, Vec<Application>>,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -32,6 +39,13 @@ pub struct TargetPackage {
     pub package: Application,
 }
 
+#[derive(Clone, Debug)]
+pub struct SearchHit {
+    pub repo: String,
+    pub package: Application,
+    pub score: u32,
+}
+
 impl BinDatabase {
     pub fn new() -> Self {
         Self {
@@ -39,22 +53,31 @@ impl BinDatabase {
         }
     }
 
+    pub fn target(
+        &self,
+        config: &Configuration,
+        repo_name: &str,
+        application: &Application,
+    ) -> Option<TargetPackage> {
+        let repo_address = config.get_static_address(repo_name)?;
+        let package_name = format!("{}.app", &application.archive_name());
+        let package_address: Url = Url::parse(&repo_address).ok()?;
+        // "./" keeps an epoch's colon from reading as a URL scheme
+        let full_address = package_address.join(&format!("./{}", package_name)).ok()?;
+
+        Some(TargetPackage {
+            repo: repo_name.to_string(),
+            package_address: full_address.to_string(),
+            package: application.clone(),
+        })
+    }
+
     pub fn find(&self, config: &Configuration, app: &str) -> Option<TargetPackage> {
         let mut res: Option<TargetPackage> = None;
         for (repo_name, repo) in self.repos.iter() {
-            if let Some(repo_address) = config.get_static_address(&repo_name) {
-                if let Some(application) = repo.applications.get(app) {
-                    let package_name = format!("{}.app", &application.archive_name());
-                    let package_address: Url = Url::parse(&repo_address).unwrap();
-                    let full_address = package_address.join(&package_name).unwrap();
-
-                    println!("{:#?}", package_address.as_str());
-
-                    res = Some(TargetPackage {
-                        repo: repo_name.clone(),
-                        package_address: format!("{}", full_address.to_string()),
-                        package: application.clone(),
-                    })
+            if config.get_static_address(repo_name).is_some() {
+                if let Some(application) = repo.get(app) {
+                    res = self.target(config, repo_name, &application);
                 }
             } else {
                 println!("Failed to get repo address: {}", &repo_name);
@@ -64,240 +87,397 @@ impl BinDatabase {
         res
     }
 
-    pub async fn install(
-        &self,
-        rd: &DepGraph<String>,
-        repo_config: &Configuration,
-        packages: Vec<String>,
-    ) -> Result<(), Vec<String>> {
-        let mut not_found_packages: Vec<String> = Vec::new();
-        let mut to_install_name: Vec<String> = Vec::new();
-        let mut to_install: Vec<TargetPackage> = Vec::new();
-        let mut missing_from_db: Vec<String> = Vec::new();
-        // let to_downloads: Vec<String> = Vec::new();
+    /// The package from the first repo, in config order, that has it.
+    pub fn lookup(&self, config: &Configuration, app: &str) -> Option<TargetPackage> {
+        config.repos.iter().find_map(|repo_meta| {
+            let repo = self.repos.get(&repo_meta.name)?;
+            let application = repo.get(app)?;
+            self.target(config, &repo_meta.name, &application)
+        })
+    }
 
-        for package in packages.iter() {
-            if let None = &self.find(repo_config, package) {
-                not_found_packages.push(package.to_string());
-            }
+    /// Match every term, as a case-insensitive regex or else as plain text,
+    /// against package names and descriptions. Best matches come first:
+    /// exact names, then name prefixes, names and finally descriptions.
+    pub fn search(
+        &self,
+        config: &Configuration,
+        terms: &[String],
+    ) -> Result<Vec<SearchHit>, String> {
+        let mut patterns: Vec<Regex> = Vec::new();
+        for term in terms.iter() {
+            let pattern = match Regex::new(&format!("(?i){}", term)) {
+                Ok(r) => r,
+                Err(_) => Regex::new(&format!("(?i){}", regex::escape(term)))
+                    .map_err(|e| e.to_string())?,
+            };
+            patterns.push(pattern);
         }
 
-        if !not_found_packages.is_empty() {
-            println!(
-                "Unable to find {singplu}: {list}",
-                singplu = if not_found_packages.len() > 1 {
-                    "packages"
-                } else {
-                    "package"
-                },
-                list = not_found_packages.join(", ")
-            );
-
-            std::process::exit(1);
-        } else {
-            for package in packages.iter() {
-                if let Ok(nodes) = rd.dependencies_of(package) {
-                    for node in nodes {
-                        to_install_name.push(node.unwrap().to_string())
+        let mut hits: Vec<SearchHit> = Vec::new();
+        for repo_meta in config.repos.iter() {
+            if let Some(repo) = self.repos.get(&repo_meta.name) {
+                for entry in repo.entries().iter() {
+                    let name = &entry.name;
+                    let description = entry.description.clone().unwrap_or_default();
+                    let mut score = 0;
+                    for (term, pattern) in terms.iter().zip(patterns.iter()) {
+                        score += if name.eq_ignore_ascii_case(term) {
+                            100
+                        } else if pattern.find(name).is_some_and(|m| m.start() == 0) {
+                            50
+                        } else if pattern.is_match(name) {
+                            25
+                        } else if pattern.is_match(&description) {
+                            10
+                        } else {
+                            score = 0;
+                            break;
+                        };
                     }
-                }
-            }
-            println!("{:?}", to_install_name);
-            if !to_install_name.is_empty() {
-                to_install_name.dedup();
-
-                for name in to_install_name.iter() {
-                    if let Some(pkg) = self.find(&repo_config, name) {
-                        to_install.push(pkg.clone())
-                    } else {
-                        missing_from_db.push(name.to_string())
+                    if score > 0 {
+                        if let Some(package) = repo.get(name) {
+                            hits.push(SearchHit {
+                                repo: repo_meta.name.clone(),
+                                package,
+                                score,
+                            });
+                        }
                     }
                 }
+            }
+        }
+        hits.sort_by(|a, b| {
+            b.score
+                .cmp(&a.score)
+                .then_with(|| a.package.metadata.name.cmp(&b.package.metadata.name))
+        });
+        Ok(hits)
+    }
 
-                if !missing_from_db.is_empty() {
-                    println!(
-                        "Following {singplu} are missing from database: : {list}",
-                        singplu = if missing_from_db.len() > 1 {
-                            "packages"
-                        } else {
-                            "package"
-                        },
-                        list = missing_from_db.join(", ")
-                    );
-                } else {
-                    // Download packages
-                    println!("{}", "DOWNLOADING PACKAGES".green());
-
-                    for target in to_install.iter() {
-                        let file_path = CACHE_DIR
-                            .to_path_buf()
-                            .join(format!("{}.app", &target.package.archive_name()));
-                        download_http(
-                            file_path.to_str().unwrap(),
-                            &target.package.metadata.name,
-                            &target.package_address,
-                        )
-                        .await
-                        .unwrap();
+    /// Look a name up as a package first, then as a virtual name provided
+    /// by other packages. An installed provider wins over the others.
+    pub fn find_provider(&self, config: &Configuration, name: &str) -> Option<TargetPackage> {
+        if let Some(target) = self.lookup(config, name) {
+            return Some(target);
+        }
+        let providers = self.providers_of(config, name);
+        match self.installed_provider(name) {
+            Some(app) => providers
+                .into_iter()
+                .find(|t| t.package.metadata.name == app.metadata.name),
+            None => providers.into_iter().next(),
+        }
+    }
+
+    /// Packages providing `name`, repo by repo in config order and by name
+    /// within a repo. A package several repos carry comes from the first.
+    pub fn providers_of(&self, config: &Configuration, name: &str) -> Vec<TargetPackage> {
+        let mut res: Vec<TargetPackage> = Vec::new();
+        for repo_meta in config.repos.iter() {
+            if let Some(repo) = self.repos.get(&repo_meta.name) {
+                let mut found: Vec<Application> = repo
+                    .providers(name)
+                    .into_iter()
+                    .filter(|app| app.provides_name(name))
+                    .collect();
+                found.sort_by(|a, b| a.metadata.name.cmp(&b.metadata.name));
+                for app in found.iter() {
+                    if res
+                        .iter()
+                        .any(|t| t.package.metadata.name == app.metadata.name)
+                    {
+                        continue;
                     }
-                    println!("{}", "INSTALLING PACKAGES".green());
-                    let pb = ProgressBar::new(to_install.len() as u64);
-                    for target in to_install.iter() {
-                        let file_path = CACHE_DIR
-                            .to_path_buf()
-                            .join(format!("{}.app", &target.package.archive_name()));
-                        let target_str = file_path.to_str().unwrap().to_string();
-
-                        decompress_zstd(&target_str).unwrap();
-                        extract_archive(
-                            &target_str.trim_end_matches(&SUFFIX_APP.to_string()),
-                            ROOT_DIR.to_str().unwrap(),
-                        )
-                        .unwrap();
-
-                        std::fs::remove_file(&target_str).unwrap();
-                        std::fs::remove_file(&target_str.trim_end_matches(&SUFFIX_APP.to_string()))
-                            .unwrap();
-                        pb.inc(1);
+                    if let Some(t) = self.target(config, &repo_meta.name, app) {
+                        res.push(t);
                     }
-                    pb.finish();
                 }
             }
         }
+        res
+    }
+
+    pub fn installed_provider(&self, name: &str) -> Option<Application> {
+        self.list_installed()
+            .unwrap_or_default()
+            .into_iter()
+            .find(|app| app.provides_name(name))
+    }
+
+    pub async fn install(
+        &self,
+        repo_config: &Configuration,
+        packages: Vec<String>,
+        overwrite: &[String],
+    ) -> Result<(), Vec<String>> {
+        let plan = Resolver::new(self, repo_config).resolve(&packages)?;
+        if plan.is_empty() {
+            println!("{}", "Nothing to do.".green());
+            return Ok(());
+        }
+        plan.print();
+        let to_install = plan.targets();
+        let requested = dependency_names(&packages);
+
+        // Download packages
+        println!("{}", "DOWNLOADING PACKAGES".green());
+        let keyring = Keyring::load();
+
+        let mut downloads: Vec<(&TargetPackage, Download)> = Vec::new();
+        for target in to_install.iter() {
+            let level = repo_config.get_sig_level(&target.repo);
+            if cached(&target.package, &keyring, level).is_some() {
+                println!("=> {} {}", target.package.metadata.name, "cached".green());
+                continue;
+            }
+            if target.repo == CACHE_REPO {
+                return Err(vec![format!(
+                    "{}: cached package {} did not pass its checks",
+                    target.package.metadata.name,
+                    cache_path(&target.package).display()
+                )]);
+            }
+            let download = Download {
+                name: target.package.metadata.name.clone(),
+                address: target.package_address.clone(),
+                file: cache_path(&target.package),
+                size: target.package.size,
+            };
+            downloads.push((target, download));
+        }
+        if !downloads.is_empty() {
+            download_all(
+                downloads.iter().map(|(_, d)| d.clone()).collect(),
+                repo_config.parallel_downloads,
+            )
+            .await?;
+        }
+        for (target, download) in downloads.iter() {
+            let verified = match check_file(&target.package, &download.file) {
+                Ok(_) => {
+                    verify_download(
+                        &keyring,
+                        repo_config.get_sig_level(&target.repo),
+                        &download.file,
+                        &target.package_address,
+                    )
+                    .await
+                }
+                Err(e) => Err(e),
+            };
+            if let Err(e) = verified {
+                remove_file(&download.file).ok();
+                remove_file(sig_path(&download.file)).ok();
+                return Err(vec![e]);
+            }
+        }
+        println!("{}", "INSTALLING PACKAGES".green());
+        let mut transaction = match Transaction::new() {
+            Ok(t) => t,
+            Err(e) => return Err(vec![e.to_string()]),
+        };
+        if let Err(e) = transaction.set_overwrite(overwrite) {
+            transaction.finish().ok();
+            return Err(vec![e.to_string()]);
+        }
+        for app in plan.removals().into_iter() {
+            transaction.remove(app);
+        }
+        let pb = ProgressBar::new(to_install.len() as u64);
+        for target in to_install.iter() {
+            let file_path = cache_path(&target.package);
+            let target_str = file_path.to_str().unwrap().to_string();
+
+            let name = &target.package.metadata.name;
+            if let Err(e) = transaction
+                .stage(&target_str)
+                .map(|_| ())
+                .and_then(|_| transaction.set_reason(name, plan.reason(name, &requested)))
+            {
+                transaction.finish().ok();
+                return Err(vec![format!("{}: {}", name, e)]);
+            }
+            pb.inc(1);
+        }
+        pb.finish();
+        transaction.run()?;
 
         Ok(())
     }
 
     pub async fn install_files(
         &self,
-        _rd: &DepGraph<String>,
-        _repo_config: &Configuration,
+        repo_config: &Configuration,
         packages: Vec<PathBuf>,
-    ) -> std::io::Result<()> {
-        let mut tar_files: Vec<String> = Vec::new();
-
+        overwrite: &[String],
+    ) -> Result<(), Vec<String>> {
+        let mut files: Vec<(String, Application)> = Vec::new();
         for package in packages.iter() {
-            decompress_zstd(&package.to_str().unwrap()).unwrap();
-            tar_files.push(
-                package
-                    .to_str()
-                    .unwrap()
-                    .to_string()
-                    .trim_end_matches(".app")
-                    .to_string(),
-            );
-        }
-        let mut valid_packages: Vec<String> = Vec::new();
-        for tar_file in tar_files.iter() {
-            let file = File::open(tar_file).unwrap();
-            let mut archive = tar::Archive::new(file);
-
-            match archive.entries() {
-                Ok(mut entries) => {
-                    let res = entries.find(|f| {
-                        f.as_ref().unwrap().path().unwrap().to_str().unwrap() == "manifest.yml"
-                    });
-                    let mut buf = String::new();
-                    res.unwrap().unwrap().read_to_string(&mut buf).unwrap();
-
-                    let data: Result<Application, serde_yaml::Error> = serde_yaml::from_str(&buf);
-                    match data {
-                        Ok(_) => valid_packages.push(tar_file.clone()),
-                        Err(e) => {
-                            println!("{}", e.to_string());
-                            std::process::exit(1);
-                        }
-                    }
-                }
-                Err(e) => {
-                    println!("{}", e.to_string());
-                    std::process::exit(1);
-                }
+            match read_manifest(package) {
+                Ok(app) => files.push((package.display().to_string(), app)),
+                Err(e) => return Err(vec![format!("{}: {}", package.display(), e)]),
             }
         }
-        println!("{}", "INSTALLING PACKAGES".green());
-        let pb = ProgressBar::new(valid_packages.len() as u64);
+        let plan = Resolver::new(self, repo_config).resolve_files(&files)?;
+        plan.print();
+        let requested: Vec<String> = files
+            .iter()
+            .map(|(_, app)| app.metadata.name.clone())
+            .collect();
 
-        for package in valid_packages.iter() {
-            extract_archive(&package, ROOT_DIR.to_str().unwrap()).unwrap();
+        println!("{}", "INSTALLING PACKAGES".green());
+        let mut transaction = match Transaction::new() {
+            Ok(t) => t,
+            Err(e) => return Err(vec![e.to_string()]),
+        };
+        if let Err(e) = transaction.set_overwrite(overwrite) {
+            transaction.finish().ok();
+            return Err(vec![e.to_string()]);
+        }
+        for app in plan.removals().into_iter() {
+            transaction.remove(app);
+        }
+        let to_install = plan.targets();
+        let pb = ProgressBar::new(to_install.len() as u64);
+        for target in to_install.iter() {
+            let name = &target.package.metadata.name;
+            if let Err(e) = transaction
+                .stage(&target.package_address)
+                .map(|_| ())
+                .and_then(|_| transaction.set_reason(name, plan.reason(name, &requested)))
+            {
+                transaction.finish().ok();
+                return Err(vec![format!("{}: {}", target.package_address, e)]);
+            }
             pb.inc(1);
         }
         pb.finish();
+        transaction.run()
+    }
 
-        Ok(())
+    pub fn remove(
+        &self,
+        repo_config: &Configuration,
+        packages: &[String],
+        cascade: bool,
+        recursive: bool,
+    ) -> Result<(), Vec<String>> {
+        let plan = Resolver::new(self, repo_config).removal(packages, cascade, recursive)?;
+        plan.print();
+
+        println!("{}", "REMOVING PACKAGES".green());
+        let mut transaction = match Transaction::new() {
+            Ok(t) => t,
+            Err(e) => return Err(vec![e.to_string()]),
+        };
+        for app in plan.removals().into_iter() {
+            transaction.remove(app);
+        }
+        transaction.run()
+    }
+
+    /// Remove dependencies that no explicitly installed package needs.
+    pub fn autoremove(&self) -> Result<(), Vec<String>> {
+        let orphans = orphans(&self.list_installed().unwrap_or_default());
+        if orphans.is_empty() {
+            println!("{}", "Nothing to do.".green());
+            return Ok(());
+        }
+        let plan = Plan {
+            steps: orphans.iter().cloned().map(Step::Remove).collect(),
+        };
+        plan.print();
+
+        println!("{}", "REMOVING PACKAGES".green());
+        let mut transaction = match Transaction::new() {
+            Ok(t) => t,
+            Err(e) => return Err(vec![e.to_string()]),
+        };
+        for app in orphans.into_iter() {
+            transaction.remove(app);
+        }
+        transaction.run()
     }
 
-    pub async fn update(&self, rd: &DepGraph<String>, config: &Configuration) {
+    /// Refresh the database of every repo and print how each went. Repos
+    /// that fail keep their old database. True when all of them synced.
+    pub async fn sync(&mut self, config: &Configuration, force: bool) -> bool {
+        let keyring = Keyring::load();
+        let mut synced = true;
         for repo in config.repos.iter() {
-            let version = reqwest::get(&repo.update_address)
-                .await
-                .unwrap()
-                .text()
-                .await
-                .unwrap();
-
-            let online_version: u64 = version.parse().unwrap();
-            let local_version: u64 = self
-                .repos
-                .get(&repo.name)
-                .unwrap()
-                .date
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap()
-                .as_secs();
-
-            if online_version > local_version {
-                let db_file_path = SYNC_DIR.join(format!("{}.db", &repo.name));
-                let address = format!("{}/{}.db", &repo.static_address, &repo.name);
-                remove_file(&db_file_path).unwrap();
-                download_http(
-                    db_file_path.to_str().unwrap(),
-                    &format!("{}.db", &repo.name),
-                    &address,
-                )
-                .await
-                .unwrap();
-
-                let mut to_update: Vec<String> = Vec::new();
-                if let Some(apps) = self.list_installed() {
-                    if !apps.is_empty() {
-                        apps.iter().for_each(|f| {
-                            let install_v = Version::parse(&f.metadata.version).unwrap();
-                            let let_in_db = self.find(&config, &f.metadata.name).unwrap();
-                            let dbv = Version::parse(&let_in_db.package.metadata.version).unwrap();
-
-                            if dbv.gt(&install_v) {
-                                to_update.push(f.metadata.name.to_string());
-                            }
-                        })
-                    }
-                }
-                if !to_update.is_empty() {
-                    self.install(rd, &config, to_update).await.unwrap()
+            let status = sync_repo(repo, &keyring, force).await;
+            println!("{} {}", repo.name.bold(), status);
+            match status {
+                SyncStatus::Updated(data) => {
+                    self.repos.insert(repo.name.clone(), data);
                 }
-            } else {
-                println!("{}", "Your system is already up to date.".green())
+                SyncStatus::Failed(_) => synced = false,
+                SyncStatus::UpToDate => {}
             }
         }
+        synced
     }
 
-    pub fn list_installed(&self) -> Option<Vec<Application>> {
-        let mut res: Vec<Application> = Vec::new();
-        for entry in WalkDir::new(LOCAL_DIR.as_path())
-            .min_depth(1)
-            .max_depth(1)
-            .sort_by(|a, b| a.file_name().cmp(b.file_name()))
-        {
-            let app_path = entry.unwrap().path().join("manifest.yml");
-            if app_path.exists() {
-                let file = File::open(app_path).unwrap();
-                match serde_yaml::from_reader(file) {
-                    Ok(d) => res.push(d),
-                    Err(_) => {}
+    /// Installed packages with a newer version in the first repo that has
+    /// them, and packages replacing installed ones. Held packages are left
+    /// out with a warning.
+    pub fn outdated(&self, config: &Configuration) -> Vec<String> {
+        let installed = self.list_installed().unwrap_or_default();
+        let mut to_update: Vec<String> = Vec::new();
+        for app in installed.iter() {
+            if let Some(t) = self.lookup(config, &app.metadata.name) {
+                if PkgVersion::of(&t.package.metadata) > PkgVersion::of(&app.metadata) {
+                    if config.is_held(&app.metadata.name) {
+                        println!(
+                            "{} {} {} ({} available)",
+                            "warning: held".yellow(),
+                            app.metadata.name,
+                            app.metadata.full_version(),
+                            t.package.metadata.full_version()
+                        );
+                        continue;
+                    }
+                    to_update.push(app.metadata.name.clone());
                 }
             }
         }
+        // renamed packages
+        for repo_meta in config.repos.iter() {
+            let repo = match self.repos.get(&repo_meta.name) {
+                Some(r) => r,
+                None => continue,
+            };
+            for old in installed.iter() {
+                if config.is_held(&old.metadata.name) {
+                    continue;
+                }
+                for app in repo.replacing(&old.metadata.name).into_iter() {
+                    let name = app.metadata.name;
+                    if !installed.iter().any(|a| a.metadata.name == name)
+                        && !to_update.contains(&name)
+                    {
+                        to_update.push(name);
+                    }
+                }
+            }
+        }
+        to_update
+    }
+
+    /// Bring all installed packages up to the versions in the local
+    /// databases in one transaction.
+    pub async fn upgrade(&self, config: &Configuration) -> Result<(), Vec<String>> {
+        let to_update = self.outdated(config);
+        if to_update.is_empty() {
+            println!("{}", "Your system is already up to date.".green());
+            return Ok(());
+        }
+        self.install(config, to_update, &[]).await
+    }
 
+    pub fn list_installed(&self) -> Option<Vec<Application>> {
+        let res = installed();
         if !res.is_empty() {
             Some(res)
         } else {
@@ -312,6 +492,7 @@ impl BinRepo {
         Self {
             applications: HashMap::new(),
             date: SystemTime::now(),
+            archive: HashMap::new(),
         }
     }
 
@@ -321,76 +502,32 @@ impl BinRepo {
         data
     }
 
-    // Dependencies
-    pub fn get_run_dependencies(&self) -> DepGraph<String> {
-        let mut depgraph: DepGraph<String> = DepGraph::new();
-        if !self.applications.is_empty() {
-            for (_name, app) in self.applications.iter() {
-                let name = app.metadata.name.to_string();
-                if let Some(deps) = &app.dependencies {
-                    if let Some(run_deps) = &deps.run_dependencies {
-                        let rdeps = run_deps;
-                        depgraph.register_dependencies(name, rdeps.to_vec())
-                    }
-                } else {
-                    depgraph.register_node(name)
-                }
+    /// Add a package, keeping the newest version of each name current and
+    /// moving the other one to the archive. The same version is replaced.
+    pub fn insert(&mut self, app: Application) {
+        let name = app.metadata.name.clone();
+        let (current, older) = match self.applications.remove(&name) {
+            Some(old) if PkgVersion::of(&old.metadata) > PkgVersion::of(&app.metadata) => {
+                (old, Some(app))
             }
-        }
-        depgraph
-    }
-
-    pub fn get_opt_dependencies(&self) -> DepGraph<String> {
-        let mut depgraph: DepGraph<String> = DepGraph::new();
-        if !self.applications.is_empty() {
-            for (_name, app) in self.applications.iter() {
-                let name = app.metadata.name.to_string();
-                if let Some(deps) = &app.dependencies {
-                    if let Some(run_deps) = &deps.opt_dependencies {
-                        let rdeps = run_deps;
-                        depgraph.register_dependencies(name, rdeps.to_vec())
-                    }
-                } else {
-                    depgraph.register_node(name)
-                }
+            Some(old) if PkgVersion::of(&old.metadata) < PkgVersion::of(&app.metadata) => {
+                (app, Some(old))
             }
+            _ => (app, None),
+        };
+        if let Some(older) = older {
+            let versions = self.archive.entry(name.clone()).or_default();
+            versions.retain(|a| PkgVersion::of(&a.metadata) != PkgVersion::of(&older.metadata));
+            versions.push(older);
+            versions.sort_by_key(|a| PkgVersion::of(&a.metadata));
         }
-        depgraph
+        self.applications.insert(name, current);
     }
 
-    pub fn get_build_dependencies(&self) -> DepGraph<String> {
-        let mut depgraph: DepGraph<String> = DepGraph::new();
-        if !self.applications.is_empty() {
-            for (_name, app) in self.applications.iter() {
-                let name = app.metadata.name.to_string();
-                if let Some(deps) = &app.dependencies {
-                    if let Some(run_deps) = &deps.build_dependencies {
-                        let rdeps = run_deps;
-                        depgraph.register_dependencies(name, rdeps.to_vec())
-                    }
-                } else {
-                    depgraph.register_node(name)
-                }
-            }
-        }
-        depgraph
-    }
-
-    pub fn get_test_dependencies(&self) -> DepGraph<String> {
-        let mut depgraph: DepGraph<String> = DepGraph::new();
-        if !self.applications.is_empty() {
-            for (_name, app) in self.applications.iter() {
-                let name = app.metadata.name.to_string();
-                if let Some(deps) = &app.dependencies {
-                    if let Some(run_deps) = &deps.test_dependencies {
-                        let rdeps = run_deps;
-                        depgraph.register_dependencies(name, rdeps.to_vec())
-                    }
-                } else {
-                    depgraph.register_node(name)
-                }
-            }
-        }
-        depgraph
+    /// Take a package out with all of its versions.
+    pub fn remove(&mut self, name: &str) -> Vec<Application> {
+        let mut removed: Vec<Application> = self.archive.remove(name).unwrap_or_default();
+        removed.extend(self.applications.remove(name));
+        removed
     }
 }
@@ -1,17 +1,19 @@
 use crate::Configuration;
 
 use crate::{
-    statics::{MANI_FILE, PKG_DIR, SRC_DIR},
-    utils::{create_archive, decompress_all, download_git, download_http, read_to_vec_u8},
-    Application, BinDatabase, Dependency, Deployment, Function, Metadata, Security, Source,
+    check::hash_file,
+    statics::{PKG_DIR, SRC_DIR},
+    utils::{create_archive, decompress_all, download_git, download_http},
+    Application, BinDatabase, Constraint, Dependency, Deployment, FileEntry, FileKind, Function,
+    Hooks, Metadata, Security, Source,
 };
 use colored::Colorize;
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
-use solvent::DepGraph;
 use std::{
-    fs::File,
+    collections::HashMap,
+    fs::{read_link, File},
     io::{Error, ErrorKind},
+    os::unix::fs::MetadataExt,
     path::PathBuf,
 };
 use url::Url;
@@ -23,6 +25,13 @@ pub struct BuildFile {
     pub sources: Option<Vec<Source>>,
     pub security: Option<Security>,
     pub dependencies: Option<Dependency>,
+    // Config files, relative to ROOT_DIR, whose local changes survive upgrades.
+    pub backup: Option<Vec<String>>,
+    // Scripts run on the target system when the package is installed,
+    // upgraded or removed.
+    pub hooks: Option<Hooks>,
+    // "<uid>:<gid>" of paths, relative to PKG_DIR, that don't belong to root.
+    pub owners: Option<HashMap<String, String>>,
     pub prepare: Option<Function>,
     pub build: Option<Function>,
     pub check: Option<Function>,
@@ -42,6 +51,9 @@ impl BuildFile {
             }]),
             security: None,
             dependencies: None,
+            backup: None,
+            hooks: None,
+            owners: None,
             prepare: None,
             build: None,
             check: None,
@@ -72,13 +84,8 @@ impl BuildFile {
         *self = data;
     }
 
-    pub async fn check_build_dependencies(
-        &self,
-        rd: &DepGraph<String>,
-        config: &Configuration,
-        bdb: &BinDatabase,
-    ) {
-        let mut not_installed_deps: Vec<String> = Vec::new();
+    pub async fn check_build_dependencies(&self, config: &Configuration, bdb: &BinDatabase) {
+        let mut not_installed_deps: Vec<Constraint> = Vec::new();
         let mut to_install_deps: Vec<String> = Vec::new();
         let mut missing_deps: Vec<String> = Vec::new();
 
@@ -87,16 +94,35 @@ impl BuildFile {
                 if !build_deps.is_empty() {
                     // 1. Check is make deps installed
                     for bd in build_deps.iter() {
-                        if let None = Application::is_installed(bd) {
-                            not_installed_deps.push(bd.to_string())
+                        let constraint = match Constraint::parse(bd) {
+                            Ok(c) => c,
+                            Err(e) => {
+                                eprintln!("{}", e.red());
+                                std::process::exit(1);
+                            }
+                        };
+                        match Application::is_installed(&constraint.name) {
+                            Some(app) if constraint.satisfied_by(&app.metadata.full_version()) => {}
+                            _ => not_installed_deps.push(constraint),
                         }
                     }
 
                     if !not_installed_deps.is_empty() {
                         for bdep in not_installed_deps.iter() {
-                            match bdb.find(config, bdep) {
-                                Some(_) => to_install_deps.push(bdep.clone()),
-                                None => missing_deps.push(bdep.clone()),
+                            match bdb.find_provider(config, &bdep.name) {
+                                Some(t)
+                                    if t.package
+                                        .provided_version(&bdep.name)
+                                        .is_some_and(|v| bdep.satisfied_by(&v)) =>
+                                {
+                                    to_install_deps.push(bdep.name.clone())
+                                }
+                                Some(t) => missing_deps.push(format!(
+                                    "{} (repo has {})",
+                                    bdep,
+                                    t.package.metadata.full_version()
+                                )),
+                                None => missing_deps.push(bdep.to_string()),
                             }
                         }
                     }
@@ -106,7 +132,10 @@ impl BuildFile {
                             // for app in to_install_deps.iter() {
                             //     // install the dep there
                             // }
-                            bdb.install(&rd, config, to_install_deps).await.unwrap();
+                            if let Err(errors) = bdb.install(config, to_install_deps, &[]).await {
+                                errors.iter().for_each(|e| eprintln!("{}", e.red()));
+                                std::process::exit(1);
+                            }
                         }
                     } else {
                         println!(
@@ -222,17 +251,12 @@ impl BuildFile {
         Ok(())
     }
 
-    pub async fn build_all(
-        &self,
-        rd: &DepGraph<String>,
-        config: &Configuration,
-        bdb: &BinDatabase,
-    ) {
-        &self.check_build_dependencies(&rd, &config, &bdb).await;
+    pub async fn build_all(&self, config: &Configuration, bdb: &BinDatabase) {
+        self.check_build_dependencies(config, bdb).await;
         match &self.pull_all().await {
             Ok(_) => match self.build() {
-                Ok(_) => match self.to_app().write() {
-                    Ok(_) => self.create_package(),
+                Ok(_) => match self.create_package() {
+                    Ok(_) => {}
                     Err(e) => {
                         eprintln!("{}", &e.to_string().red());
                         std::process::exit(1);
@@ -252,50 +276,119 @@ impl BuildFile {
 
     pub fn archive_name(&self) -> String {
         format!(
-            "{}-{}-{}-{}",
-            self.metadata.name, self.metadata.version, self.metadata.release, "x86_64"
+            "{}-{}-{}",
+            self.metadata.name,
+            self.metadata.full_version(),
+            "x86_64"
         )
     }
 
-    pub fn gen_file_list(&self) -> Vec<String> {
-        if MANI_FILE.exists() {
-            std::fs::remove_file(MANI_FILE.to_path_buf()).unwrap()
-        }
-
-        let mut files: Vec<String> = Vec::new();
+    // Every file, directory and link in PKG_DIR as it is to be installed.
+    // Everything belongs to root unless `owners` says otherwise, the mode is
+    // taken as is, setuid bits included. Further names of a file already
+    // listed become hardlinks to it.
+    pub fn gen_file_list(&self) -> std::io::Result<Vec<FileEntry>> {
+        let owners = self.owners.clone().unwrap_or_default();
+        let mut files: Vec<FileEntry> = Vec::new();
+        let mut inodes: HashMap<(u64, u64), String> = HashMap::new();
 
-        for entry in WalkDir::new(PKG_DIR.to_path_buf()).min_depth(1) {
-            let entry = entry.unwrap();
-            if entry.metadata().unwrap().is_file() {
-                let shahash = read_to_vec_u8(&entry.path());
+        for entry in WalkDir::new(PKG_DIR.to_path_buf())
+            .min_depth(1)
+            .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+        {
+            let entry = entry?;
+            let meta = entry.path().symlink_metadata()?;
+            let path = entry
+                .path()
+                .strip_prefix(PKG_DIR.as_path())
+                .unwrap()
+                .to_string_lossy()
+                .to_string();
 
-                let mut hasher = Sha256::new();
-                hasher.update(&shahash);
+            let file_type = meta.file_type();
+            let mut file = if file_type.is_dir() {
+                FileEntry::new(path.clone(), FileKind::Dir)
+            } else if file_type.is_symlink() {
+                let mut link = FileEntry::new(path.clone(), FileKind::Symlink);
+                link.target = Some(read_link(entry.path())?.to_string_lossy().to_string());
+                link
+            } else if file_type.is_file() {
+                match inodes.get(&(meta.dev(), meta.ino())) {
+                    Some(first) => {
+                        let mut link = FileEntry::new(path.clone(), FileKind::Hardlink);
+                        link.target = Some(first.clone());
+                        link
+                    }
+                    None => {
+                        if meta.nlink() > 1 {
+                            inodes.insert((meta.dev(), meta.ino()), path.clone());
+                        }
+                        let mut file = FileEntry::new(path.clone(), FileKind::File);
+                        file.sha256 = hash_file(entry.path())?;
+                        file
+                    }
+                }
+            } else {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "{}: only files, directories and links can be packaged",
+                        entry.path().display()
+                    ),
+                ));
+            };
 
-                let mut buf = entry
-                    .path()
-                    .display()
-                    .to_string()
-                    .trim_start_matches(PKG_DIR.to_str().unwrap())
-                    .trim_start_matches("/")
-                    .to_string();
-                buf.push_str(&format!(" {:x}", hasher.finalize()));
-                files.push(buf);
+            if !file_type.is_symlink() {
+                file.mode = Some(meta.mode() & 0o7777);
             }
+            let (uid, gid) = match owners.get(&path) {
+                Some(owner) => parse_owner(owner).ok_or_else(|| {
+                    Error::new(
+                        ErrorKind::InvalidData,
+                        format!("owner of {} is not uid:gid: {}", path, owner),
+                    )
+                })?,
+                None => (0, 0),
+            };
+            file.uid = Some(uid);
+            file.gid = Some(gid);
+            files.push(file);
         }
 
-        files
+        for path in owners.keys() {
+            if !files.iter().any(|f| &f.path == path) {
+                return Err(Error::new(
+                    ErrorKind::NotFound,
+                    format!("owners: {} is not in the package", path),
+                ));
+            }
+        }
+        Ok(files)
     }
-    pub fn to_app(&self) -> Application {
-        Application {
+    pub fn to_app(&self) -> std::io::Result<Application> {
+        Ok(Application {
             metadata: self.metadata.clone(),
             security: self.security.clone(),
             dependencies: self.dependencies.clone(),
-            files: self.gen_file_list(),
-        }
+            backup: self.backup.clone(),
+            hooks: self.hooks.clone(),
+            files: self.gen_file_list()?,
+            size: None,
+            sha256: None,
+        })
     }
 
-    pub fn create_package(&self) {
-        create_archive(&self, PKG_DIR.to_path_buf())
+    /// Write manifest.yml next to pkgbuild.yml and pack PKG_DIR with it.
+    pub fn create_package(&self) -> std::io::Result<()> {
+        let app = self.to_app()?;
+        app.write()?;
+        create_archive(&app, PKG_DIR.to_path_buf())
     }
 }
+
+// "<uid>:<gid>", numeric since names differ between the build host and the
+// target system.
+fn parse_owner(owner: &str) -> Option<(u32, u32)> {
+    let (uid, gid) = owner.split_once(':')?;
+    Some((uid.trim().parse().ok()?, gid.trim().parse().ok()?))
+}
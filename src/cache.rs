@@ -0,0 +1,217 @@
+use crate::{
+    check::hash_file,
+    signature::{sig_path, verify_local},
+    utils::read_manifest,
+    Application, Keyring, PkgVersion, SigLevel, CACHE_DIR,
+};
+use std::{
+    fs,
+    io::Result,
+    path::{Path, PathBuf},
+};
+
+// Repo name of packages that are only found in the cache.
+pub const CACHE_REPO: &str = "cache";
+
+// Downloaded packages stay in CACHE_DIR as "<archive name>.app" next to
+// their signature, so reinstalls and downgrades need no network.
+#[derive(Clone, Debug)]
+pub struct CachedPackage {
+    pub name: String,
+    pub version: PkgVersion,
+    pub path: PathBuf,
+    pub size: u64,
+    pub app: Application,
+}
+
+impl CachedPackage {
+    // Name and version come from the manifest, file names can't tell a
+    // dash in a name from one in a version. A file not named after its
+    // manifest is not taken for a package.
+    fn parse(path: &Path) -> Option<Self> {
+        let file_name = path.file_name()?.to_str()?;
+        if !file_name.ends_with(".app") {
+            return None;
+        }
+        let app = read_manifest(path).ok()?;
+        if file_name != format!("{}.app", app.archive_name()) {
+            return None;
+        }
+        Some(Self {
+            name: app.metadata.name.clone(),
+            version: PkgVersion::of(&app.metadata),
+            path: path.to_path_buf(),
+            size: path.metadata().ok()?.len(),
+            app,
+        })
+    }
+
+    pub fn is_installed(&self, installed: &[Application]) -> bool {
+        installed.iter().any(|app| cache_path(app) == self.path)
+    }
+}
+
+/// Where `app` is kept once downloaded.
+pub fn cache_path(app: &Application) -> PathBuf {
+    CACHE_DIR.join(format!("{}.app", app.archive_name()))
+}
+
+/// Check a package file against the size and hash in its repo database,
+/// as far as the database records them.
+pub fn check_file(app: &Application, file: &Path) -> std::result::Result<(), String> {
+    let size = file
+        .metadata()
+        .map_err(|e| format!("{}: {}", file.display(), e))?
+        .len();
+    if app.size.is_some_and(|s| s != size) {
+        return Err(format!(
+            "{}: size does not match the database",
+            file.display()
+        ));
+    }
+    if let Some(sha256) = &app.sha256 {
+        match hash_file(file) {
+            Ok(hash) if &hash == sha256 => {}
+            Ok(_) => {
+                return Err(format!(
+                    "{}: hash does not match the database",
+                    file.display()
+                ))
+            }
+            Err(e) => return Err(format!("{}: {}", file.display(), e)),
+        }
+    }
+    Ok(())
+}
+
+/// The cached copy of `app` when it can stand in for a download: the repo
+/// database has its hash and it matches, and its signature checks out as
+/// far as `level` asks.
+pub fn cached(app: &Application, keyring: &Keyring, level: SigLevel) -> Option<PathBuf> {
+    let file = cache_path(app);
+    if app.sha256.is_none() || !file.exists() || check_file(app, &file).is_err() {
+        return None;
+    }
+    if verify_local(keyring, level, &file).is_ok() {
+        Some(file)
+    } else {
+        None
+    }
+}
+
+/// Every package in the cache, by name and then version.
+pub fn list() -> Vec<CachedPackage> {
+    let mut packages: Vec<CachedPackage> = match fs::read_dir(CACHE_DIR.as_path()) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .filter_map(|e| CachedPackage::parse(&e.path()))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    packages.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.version.cmp(&b.version)));
+    packages
+}
+
+/// Every cached version of `name`, oldest first. Their size and hash are
+/// taken from the files as they are now, so nothing can swap them before
+/// they are installed.
+pub fn versions(name: &str) -> Vec<Application> {
+    list()
+        .into_iter()
+        .filter(|p| p.name == name)
+        .filter_map(|p| {
+            let mut app = p.app;
+            app.size = Some(p.size);
+            app.sha256 = Some(hash_file(&p.path).ok()?);
+            Some(app)
+        })
+        .collect()
+}
+
+/// Remove cached packages but the `keep` newest versions of each and the
+/// installed ones, or with `installed_only` all but the installed ones.
+/// Whatever else is in the cache goes too, like tarballs of an interrupted
+/// install and signatures of removed packages. Returns the removed files
+/// and the bytes freed.
+pub fn clean(
+    keep: usize,
+    installed_only: bool,
+    installed: &[Application],
+) -> Result<(Vec<PathBuf>, u64)> {
+    let packages = list();
+    let mut kept: Vec<PathBuf> = Vec::new();
+    for (i, package) in packages.iter().enumerate() {
+        // the ones after it with the same name are newer
+        let newer = packages[i + 1..]
+            .iter()
+            .take_while(|p| p.name == package.name)
+            .count();
+        if package.is_installed(installed) || (!installed_only && newer < keep) {
+            kept.push(package.path.clone());
+            kept.push(sig_path(&package.path));
+        }
+    }
+
+    let mut removed: Vec<PathBuf> = Vec::new();
+    let mut freed: u64 = 0;
+    let mut files: Vec<PathBuf> = fs::read_dir(CACHE_DIR.as_path())?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file() && !kept.contains(p))
+        .collect();
+    files.sort();
+    for file in files.into_iter() {
+        let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+        fs::remove_file(&file)?;
+        freed += size;
+        removed.push(file);
+    }
+    Ok((removed, freed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{app, temp_path};
+
+    // A package of a manifest only, as `file_name` in `dir`.
+    fn write_app(dir: &Path, file_name: &str, app: &Application) -> PathBuf {
+        let path = dir.join(file_name);
+        let manifest = serde_yaml::to_string(app).unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        let mut tar =
+            tar::Builder::new(zstd::Encoder::new(fs::File::create(&path).unwrap(), 0).unwrap());
+        tar.append_data(&mut header, "manifest.yml", manifest.as_bytes())
+            .unwrap();
+        tar.into_inner().unwrap().finish().unwrap();
+        path
+    }
+
+    #[test]
+    fn parses_archive_names() {
+        let dir = temp_path("cache");
+        fs::create_dir_all(&dir).unwrap();
+        let table = [
+            ("glibc", "2.36"),
+            ("lib-foo", "2:1.0-rc1"),
+            ("foo", "1.2.2-rc1"),
+            ("foo-1.2.2", "rc1"),
+        ];
+        for (name, version) in table.iter() {
+            let app = app(name, version, &[]);
+            let path = write_app(&dir, &format!("{}.app", app.archive_name()), &app);
+            let cached = CachedPackage::parse(&path).unwrap();
+            assert_eq!(cached.name, *name);
+            assert_eq!(cached.version.to_string(), app.metadata.full_version());
+            fs::remove_file(&path).unwrap();
+        }
+
+        // named after another package
+        let path = write_app(&dir, "foo-2.0-1-x86_64.app", &app("foo", "1.0", &[]));
+        assert!(CachedPackage::parse(&path).is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
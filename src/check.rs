@@ -0,0 +1,173 @@
+use crate::{Application, FileEntry, FileKind, ROOT_DIR};
+use sha2::{Digest, Sha256};
+use std::{
+    fmt,
+    fs::{read_link, File, Metadata},
+    io::{self, Read},
+    os::unix::fs::{MetadataExt, PermissionsExt},
+    path::Path,
+};
+
+// Something wrong with one installed file. Displayed as a tab separated
+// line `<kind>\t/<path>[\t<expected>\t<found>]` for scripts to consume.
+#[derive(Clone, Debug)]
+pub enum Problem {
+    Missing(String),
+    NotAFile(String),
+    Modified {
+        path: String,
+        expected: String,
+        found: String,
+    },
+    Mode {
+        path: String,
+        expected: u32,
+        found: u32,
+    },
+    // "<uid>:<gid>"
+    Owner {
+        path: String,
+        expected: String,
+        found: String,
+    },
+    Unreadable {
+        path: String,
+        error: String,
+    },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Missing(path) => write!(f, "missing\t/{}", path),
+            Problem::NotAFile(path) => write!(f, "type\t/{}", path),
+            Problem::Modified {
+                path,
+                expected,
+                found,
+            } => write!(f, "modified\t/{}\t{}\t{}", path, expected, found),
+            Problem::Mode {
+                path,
+                expected,
+                found,
+            } => write!(f, "mode\t/{}\t{:04o}\t{:04o}", path, expected, found),
+            Problem::Owner {
+                path,
+                expected,
+                found,
+            } => write!(f, "owner\t/{}\t{}\t{}", path, expected, found),
+            Problem::Unreadable { path, error } => write!(f, "unreadable\t/{}\t{}", path, error),
+        }
+    }
+}
+
+/// Compare every file, directory and link of an installed package with its
+/// manifest entry.
+pub fn check_package(app: &Application) -> Vec<Problem> {
+    let mut problems: Vec<Problem> = Vec::new();
+    for entry in app.files.iter() {
+        let target = ROOT_DIR.join(&entry.path);
+        let meta = match target.symlink_metadata() {
+            Ok(m) => m,
+            Err(_) => {
+                problems.push(Problem::Missing(entry.path.clone()));
+                continue;
+            }
+        };
+        let is_kind = match entry.kind {
+            FileKind::File | FileKind::Hardlink => meta.is_file(),
+            FileKind::Dir => meta.is_dir(),
+            FileKind::Symlink => meta.file_type().is_symlink(),
+        };
+        if !is_kind {
+            problems.push(Problem::NotAFile(entry.path.clone()));
+            continue;
+        }
+
+        match entry.kind {
+            FileKind::File => check_content(&target, &entry.path, &entry.sha256, &mut problems),
+            // a hardlink has the content of the file it was made from
+            FileKind::Hardlink => {
+                let first = entry.target.as_deref().and_then(|t| app.file_entry(t));
+                if let Some(first) = first {
+                    check_content(&target, &entry.path, &first.sha256, &mut problems);
+                }
+            }
+            FileKind::Symlink => {
+                let found = read_link(&target)
+                    .map(|t| t.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let expected = entry.target.clone().unwrap_or_default();
+                if found != expected {
+                    problems.push(Problem::Modified {
+                        path: entry.path.clone(),
+                        expected,
+                        found,
+                    });
+                }
+            }
+            FileKind::Dir => {}
+        }
+        check_metadata(entry, &meta, &mut problems);
+    }
+    problems
+}
+
+fn check_content(target: &Path, path: &str, sha256: &str, problems: &mut Vec<Problem>) {
+    match hash_file(target) {
+        Ok(found) if found != sha256 => problems.push(Problem::Modified {
+            path: path.to_string(),
+            expected: sha256.to_string(),
+            found,
+        }),
+        Ok(_) => {}
+        Err(e) => problems.push(Problem::Unreadable {
+            path: path.to_string(),
+            error: e.to_string(),
+        }),
+    }
+}
+
+// Mode and owner, as far as the manifest records them.
+fn check_metadata(entry: &FileEntry, meta: &Metadata, problems: &mut Vec<Problem>) {
+    if let (Some(expected), false) = (entry.mode, entry.kind == FileKind::Symlink) {
+        let found = meta.permissions().mode() & 0o7777;
+        if found != expected {
+            problems.push(Problem::Mode {
+                path: entry.path.clone(),
+                expected,
+                found,
+            });
+        }
+    }
+    if entry.uid.is_some_and(|u| u != meta.uid()) || entry.gid.is_some_and(|g| g != meta.gid()) {
+        let owner = |uid: u32, gid: u32| format!("{}:{}", uid, gid);
+        problems.push(Problem::Owner {
+            path: entry.path.clone(),
+            expected: owner(
+                entry.uid.unwrap_or_else(|| meta.uid()),
+                entry.gid.unwrap_or_else(|| meta.gid()),
+            ),
+            found: owner(meta.uid(), meta.gid()),
+        });
+    }
+}
+
+/// Hex sha256 of a file, as recorded in manifests.
+pub fn hash_file(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 65536];
+    loop {
+        let n = file.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
@@ -1,20 +1,37 @@
+use crate::{cache::CACHE_REPO, transaction::suffixed, CONF_FILE};
 use serde::{Deserialize, Serialize};
 use serde_yaml::from_reader;
-use std::{fs::File, path::PathBuf};
+use std::{
+    fs::{rename, File},
+    io::Error,
+    path::PathBuf,
+};
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Configuration {
     pub repos: Vec<RepoMeta>,
+    // How many packages are downloaded at once.
+    #[serde(default = "default_parallel_downloads")]
+    pub parallel_downloads: usize,
+    // Packages `upgrade` leaves alone, see `pi hold`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hold: Vec<String>,
 }
 
 impl Default for Configuration {
     fn default() -> Self {
         Self {
             repos: vec![RepoMeta::default()],
+            parallel_downloads: default_parallel_downloads(),
+            hold: Vec::new(),
         }
     }
 }
 
+fn default_parallel_downloads() -> usize {
+    4
+}
+
 impl Configuration {
     pub fn gen() -> Self {
         Self::default()
@@ -27,6 +44,19 @@ impl Configuration {
         data
     }
 
+    /// Write the configuration back to CONF_FILE.
+    pub fn save(&self) -> std::io::Result<()> {
+        let tmp = suffixed(CONF_FILE.as_path(), ".part");
+        if let Err(e) = serde_yaml::to_writer(File::create(&tmp)?, self) {
+            return Err(Error::other(e.to_string()));
+        }
+        rename(tmp, CONF_FILE.as_path())
+    }
+
+    pub fn is_held(&self, name: &str) -> bool {
+        self.hold.iter().any(|h| h == name)
+    }
+
     pub fn get_static_address(&self, name: &str) -> Option<String> {
         if let Some(repo) = &self.repos.iter().find(|repo| repo.name == name) {
             return Some(repo.static_address.clone());
@@ -34,6 +64,18 @@ impl Configuration {
             return None;
         }
     }
+    pub fn get_sig_level(&self, name: &str) -> SigLevel {
+        match self.repos.iter().find(|repo| repo.name == name) {
+            Some(repo) => repo.signatures,
+            // packages only found in the cache answer to the strictest repo
+            None if name == CACHE_REPO => [SigLevel::Required, SigLevel::Optional, SigLevel::Never]
+                .iter()
+                .copied()
+                .find(|l| self.repos.iter().any(|r| r.signatures == *l))
+                .unwrap_or(SigLevel::Required),
+            None => SigLevel::Required,
+        }
+    }
     pub fn get_update_address(&self, name: &str) -> Option<String> {
         if let Some(repo) = &self.repos.iter().find(|repo| repo.name == name) {
             return Some(repo.update_address.clone());
@@ -48,6 +90,20 @@ pub struct RepoMeta {
     pub name: String,
     pub static_address: String,
     pub update_address: String,
+    #[serde(default)]
+    pub signatures: SigLevel,
+}
+
+// How strictly downloads from a repo are checked against the keyring.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SigLevel {
+    // the .db and every .app must carry a valid signature
+    #[default]
+    Required,
+    // verify signatures that exist, warn about missing ones
+    Optional,
+    Never,
 }
 
 impl Default for RepoMeta {
@@ -66,6 +122,7 @@ impl Default for RepoMeta {
             name: String::from("core"),
             static_address,
             update_address,
+            signatures: SigLevel::default(),
         }
     }
 }
@@ -0,0 +1,161 @@
+use crate::PkgVersion;
+use std::{cmp::Ordering, fmt};
+
+// A dependency entry such as `glibc>=2.33`, `openssl<3` or `zlib=1.2.11`.
+// Plain names without an operator accept any version. Versions compare as
+// package versions, `[epoch:]version[-release]`, and one without a release
+// matches every release of that version.
+#[derive(Clone, Debug)]
+pub struct Constraint {
+    pub name: String,
+    pub op: String,
+    pub version: String,
+    wanted: Option<PkgVersion>,
+}
+
+impl Constraint {
+    pub fn parse(dep: &str) -> Result<Self, String> {
+        let dep = dep.trim();
+        let split = dep.find(['<', '>', '=']);
+        let (name, rest) = match split {
+            Some(pos) => (dep[..pos].trim(), dep[pos..].trim()),
+            None => (dep, ""),
+        };
+        if name.is_empty() {
+            return Err(format!("{}: missing package name", dep));
+        }
+        if rest.is_empty() {
+            return Ok(Self {
+                name: name.to_string(),
+                op: String::new(),
+                version: String::new(),
+                wanted: None,
+            });
+        }
+
+        let op_len = rest
+            .find(|c| c != '<' && c != '>' && c != '=')
+            .unwrap_or(rest.len());
+        let op = match &rest[..op_len] {
+            "==" => "=",
+            o @ "=" | o @ ">=" | o @ "<=" | o @ ">" | o @ "<" => o,
+            o => return Err(format!("{}: unknown operator {}", dep, o)),
+        };
+        let version = rest[op_len..].trim();
+        if version.is_empty() {
+            return Err(format!("{}: missing version after {}", dep, op));
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            op: op.to_string(),
+            version: version.to_string(),
+            wanted: Some(PkgVersion::parse(version)),
+        })
+    }
+
+    pub fn is_versioned(&self) -> bool {
+        self.wanted.is_some()
+    }
+
+    /// Whether a full version such as `1:2.36-2` meets this constraint.
+    pub fn satisfied_by(&self, version: &str) -> bool {
+        let wanted = match &self.wanted {
+            Some(w) => w,
+            None => return true,
+        };
+        let order = PkgVersion::parse(version).cmp(wanted);
+        match self.op.as_str() {
+            "=" => order == Ordering::Equal,
+            ">=" => order != Ordering::Less,
+            "<=" => order != Ordering::Greater,
+            ">" => order == Ordering::Greater,
+            "<" => order == Ordering::Less,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}{}", self.name, self.op, self.version)
+    }
+}
+
+// Strip the version part of every entry, for the dependency graphs.
+pub fn dependency_names(deps: &[String]) -> Vec<String> {
+    deps.iter()
+        .map(|d| match Constraint::parse(d) {
+            Ok(c) => c.name,
+            Err(_) => d.to_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parts(dep: &str) -> (String, String, String) {
+        let c = Constraint::parse(dep).unwrap();
+        (c.name, c.op, c.version)
+    }
+
+    #[test]
+    fn parses_operators() {
+        let table = [
+            ("zlib=1.2.11", "zlib", "=", "1.2.11"),
+            ("zlib==1.2.11", "zlib", "=", "1.2.11"),
+            ("glibc>=2.33", "glibc", ">=", "2.33"),
+            ("glibc<=2.33", "glibc", "<=", "2.33"),
+            ("openssl<3", "openssl", "<", "3"),
+            ("openssl>1.1", "openssl", ">", "1.1"),
+            ("glibc >= 2.33", "glibc", ">=", "2.33"),
+            ("  sh =1:5.1-2 ", "sh", "=", "1:5.1-2"),
+        ];
+        for (dep, name, op, version) in table.iter() {
+            assert_eq!(
+                parts(dep),
+                (name.to_string(), op.to_string(), version.to_string()),
+                "{}",
+                dep
+            );
+            assert!(Constraint::parse(dep).unwrap().is_versioned());
+        }
+    }
+
+    #[test]
+    fn parses_bare_names() {
+        let c = Constraint::parse(" bash ").unwrap();
+        assert_eq!((c.name.as_str(), c.op.as_str()), ("bash", ""));
+        assert!(!c.is_versioned());
+        assert!(c.satisfied_by("0.1-1"));
+        assert_eq!(c.to_string(), "bash");
+    }
+
+    #[test]
+    fn rejects_incomplete_entries() {
+        let table = [
+            (">=1.0", ">=1.0: missing package name"),
+            ("", ": missing package name"),
+            ("glibc>=", "glibc>=: missing version after >="),
+            ("glibc = ", "glibc =: missing version after ="),
+            ("glibc=>2", "glibc=>2: unknown operator =>"),
+            ("glibc<>2", "glibc<>2: unknown operator <>"),
+        ];
+        for (dep, error) in table.iter() {
+            assert_eq!(Constraint::parse(dep).unwrap_err(), *error, "{}", dep);
+        }
+    }
+
+    #[test]
+    fn checks_versions() {
+        let c = Constraint::parse("glibc>=2.33").unwrap();
+        assert!(c.satisfied_by("2.33-1"));
+        assert!(c.satisfied_by("2.36-2"));
+        assert!(!c.satisfied_by("2.4-1"));
+        let c = Constraint::parse("zlib=1.2.11").unwrap();
+        assert!(c.satisfied_by("1.2.11-3"));
+        assert!(!c.satisfied_by("1:1.2.11-3"));
+    }
+}
@@ -1,4 +1,4 @@
-use crate::Application;
+use crate::{Application, Constraint};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
@@ -7,6 +7,12 @@ pub struct Dependency {
     pub opt_dependencies: Option<Vec<String>>,
     pub run_dependencies: Option<Vec<String>>,
     pub test_dependencies: Option<Vec<String>>,
+    // Virtual names this package satisfies, e.g. `sh` for bash.
+    pub provides: Option<Vec<String>>,
+    // Packages or virtual names that can't be installed alongside this one.
+    pub conflicts: Option<Vec<String>>,
+    // Old package names this one takes over from, for renames.
+    pub replaces: Option<Vec<String>>,
 }
 
 impl Dependency {
@@ -25,9 +31,13 @@ impl Dependency {
                 if !deps.is_empty() {
                     // [x] is each dep installed or not?
                     for dep in deps.iter() {
-                        match Application::is_installed(&dep) {
-                            Some(_) => {}
-                            None => missing_deps.push(dep.to_string()),
+                        let constraint = match Constraint::parse(dep) {
+                            Ok(c) => c,
+                            Err(e) => return Err(vec![e]),
+                        };
+                        match Application::is_installed(&constraint.name) {
+                            Some(app) if constraint.satisfied_by(&app.metadata.full_version()) => {}
+                            _ => missing_deps.push(dep.to_string()),
                         }
                     }
                     // if not, check in bin repo if the package available to install
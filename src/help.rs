@@ -3,14 +3,28 @@ use colored::Colorize;
 pub fn help(option: &str) {
     match option {
         "build" => help_build(),
+        "cache" => help_cache(),
+        "check" => help_check(),
+        "db" => help_db(),
         "install" => help_install(),
+        "key" => help_key(),
+        "mark" => help_mark(),
+        "query" => help_query(),
         "remove" => help_remove(),
-        "update" => help_update(),
+        "search" => help_search(),
+        "sync" | "update" | "upgrade" | "hold" => help_update(),
         _ => {
             println!("\n{}:", "USAGE".green().bold());
             help_build();
+            help_cache();
+            help_check();
+            help_db();
             help_install();
+            help_key();
+            help_mark();
+            help_query();
             help_remove();
+            help_search();
             help_update();
         }
     }
@@ -26,6 +40,31 @@ fn help_build() {
     println!("=> {}", "store build path_to_file.yml".blue().bold(),);
 }
 
+fn help_cache() {
+    println!("\n{}", "CACHE:".magenta());
+    println!("- List downloaded packages kept for reinstalls and downgrades");
+    println!("=> {}", "store cache list".blue().bold(),);
+    println!("- Remove all but the 3 newest versions of each package and the installed ones");
+    println!("=> {}", "store cache clean".blue().bold(),);
+    println!("=> {}", "store cache clean --keep 1".blue().bold(),);
+    println!("- Remove all but the installed versions");
+    println!("=> {}", "store cache clean --installed".blue().bold(),);
+}
+
+fn help_check() {
+    println!("\n{}", "CHECK:".magenta());
+    println!("- Verify installed files against their recorded sha256, mode, owner and link target");
+    println!("=> {}", "store check".blue().bold(),);
+    println!("=> {}", "store check app_1 app_n".blue().bold(),);
+    println!("- Problems are printed as tab separated lines, the exit status is 1 if any");
+    println!(
+        "=> {}",
+        "<package> missing|type|modified|mode|owner|unreadable /<path> [expected found]"
+            .blue()
+            .bold(),
+    );
+}
+
 fn help_install() {
     println!("\n{}", "INSTALL:".magenta());
     println!("- Installation from store");
@@ -35,16 +74,86 @@ fn help_install() {
         "=> {}",
         "store install --file app_1.app app_n.app".blue().bold(),
     );
+    println!("- Replace files owned by other packages or by nobody");
+    println!(
+        "=> {}",
+        "store install --overwrite '/usr/lib/*' app_1".blue().bold(),
+    );
+    println!("- Install a given version, also an older one from the repo archive or the cache");
+    println!("=> {}", "store install app_1=1.2.2-1".blue().bold(),);
+}
+
+fn help_db() {
+    println!("\n{}", "DB:".magenta());
+    println!("- Rebuild the binary indexes of installed packages and synced repos");
+    println!("=> {}", "store db rebuild".blue().bold(),);
+    println!("- Print an index as a YAML database");
+    println!("=> {}", "store db export local".blue().bold(),);
+    println!("=> {}", "store db export core".blue().bold(),);
+}
+
+fn help_key() {
+    println!("\n{}", "KEY:".magenta());
+    println!("- Trust packages and databases signed with a public key");
+    println!("=> {}", "store key add repo.pub".blue().bold(),);
+    println!("- List or remove trusted keys");
+    println!("=> {}", "store key list".blue().bold(),);
+    println!("=> {}", "store key remove KEYID".blue().bold(),);
+}
+
+fn help_mark() {
+    println!("\n{}", "MARK:".magenta());
+    println!("- Change why packages are installed");
+    println!("=> {}", "store mark --explicit app_1 app_n".blue().bold(),);
+    println!("=> {}", "store mark --dep app_1 app_n".blue().bold(),);
+    println!("- Remove dependencies no explicitly installed package needs");
+    println!("=> {}", "store autoremove".blue().bold(),);
+}
+
+fn help_query() {
+    println!("\n{}", "QUERY:".magenta());
+    println!("- Show package details, from the local database if installed");
+    println!("=> {}", "store info app_1 app_n".blue().bold(),);
+    println!("=> {}", "store info --remote app_1".blue().bold(),);
+    println!("- List the files of a package");
+    println!("=> {}", "store files app_1".blue().bold(),);
+    println!("- Find the package owning a file");
+    println!("=> {}", "store owns /usr/bin/bash".blue().bold(),);
+    println!("- List installed packages");
+    println!("=> {}", "store list".blue().bold(),);
+    println!("- Show config files kept on upgrade next to their new .pinew version");
+    println!("=> {}", "store diff-configs".blue().bold(),);
+    println!("=> {}", "store diff-configs --list".blue().bold(),);
 }
 
 fn help_remove() {
     println!("\n{}", "REMOVE:".magenta());
     println!("- Remove give applications");
     println!("=> {}", "store remove app_1 app_n".blue().bold(),);
+    println!("- Also remove the packages depending on them");
+    println!("=> {}", "store remove --cascade app_1".blue().bold(),);
+    println!("- Also remove dependencies nothing else needs");
+    println!("=> {}", "store remove --recursive app_1".blue().bold(),);
+}
+
+fn help_search() {
+    println!("\n{}", "SEARCH:".magenta());
+    println!("- Search names and descriptions, every term is a case-insensitive regex");
+    println!("=> {}", "store search term_1 term_n".blue().bold(),);
+    println!("=> {}", "store search '^lib.*ssl'".blue().bold(),);
 }
 
 fn help_update() {
     println!("\n{}", "UPDATE:".magenta());
-    println!("- Update all install applications");
+    println!("- Refresh the database of every repo that changed on the server");
+    println!("=> {}", "store sync".blue().bold(),);
+    println!("=> {}", "store sync --force".blue().bold(),);
+    println!("- Upgrade all installed applications to the synced versions in one go");
+    println!("=> {}", "store upgrade".blue().bold(),);
+    println!("- Sync, then upgrade");
     println!("=> {}", "store update".blue().bold(),);
+    println!("- Keep upgrades away from packages, list or release held ones");
+    println!("=> {}", "store hold app_1 app_n".blue().bold(),);
+    println!("=> {}", "store hold".blue().bold(),);
+    println!("=> {}", "store unhold app_1".blue().bold(),);
 }
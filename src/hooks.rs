@@ -0,0 +1,145 @@
+use crate::{
+    statics::{HOOKS_DIR, ROOT_DIR},
+    Function,
+};
+use colored::Colorize;
+use glob::Pattern;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
+use subprocess::{Exec, ExitStatus};
+
+// Scripts run on the target system. Install hooks get the new version,
+// upgrade hooks the new and the old one, remove hooks the old one, all as
+// `version-release`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Hooks {
+    pub pre_install: Option<Function>,
+    pub post_install: Option<Function>,
+    pub pre_upgrade: Option<Function>,
+    pub post_upgrade: Option<Function>,
+    pub pre_remove: Option<Function>,
+    pub post_remove: Option<Function>,
+}
+
+impl Hooks {
+    pub fn get(&self, hook: &str) -> Option<&Function> {
+        match hook {
+            "pre_install" => self.pre_install.as_ref(),
+            "post_install" => self.post_install.as_ref(),
+            "pre_upgrade" => self.pre_upgrade.as_ref(),
+            "post_upgrade" => self.post_upgrade.as_ref(),
+            "pre_remove" => self.pre_remove.as_ref(),
+            "post_remove" => self.post_remove.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn run(&self, hook: &str, args: &[String]) -> Result<(), String> {
+        match self.get(hook) {
+            Some(f) if !f.commands.is_empty() => run_script(hook, &f.commands, args),
+            _ => Ok(()),
+        }
+    }
+}
+
+// A system wide hook from `CONF_DIR/hooks.d/*.yml`, run once after a
+// transaction that installed, upgraded or removed a matching file.
+//
+//   description: Updating linker cache
+//   operations: [install, upgrade, remove]
+//   paths: ["usr/lib/*.so*"]
+//   commands: ["ldconfig"]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Trigger {
+    #[serde(default)]
+    pub name: String,
+    pub description: Option<String>,
+    // all of them when left out
+    #[serde(default)]
+    pub operations: Vec<String>,
+    pub paths: Vec<String>,
+    pub commands: Vec<String>,
+}
+
+impl Trigger {
+    /// Every trigger definition, ordered by file name.
+    pub fn load_all() -> Vec<Trigger> {
+        let mut files: Vec<PathBuf> = match fs::read_dir(HOOKS_DIR.as_path()) {
+            Ok(entries) => entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| p.extension().is_some_and(|e| e == "yml" || e == "yaml"))
+                .collect(),
+            Err(_) => return Vec::new(),
+        };
+        files.sort();
+
+        let mut triggers: Vec<Trigger> = Vec::new();
+        for file in files.iter() {
+            let parsed = File::open(file)
+                .map_err(|e| e.to_string())
+                .and_then(|f| serde_yaml::from_reader::<_, Trigger>(f).map_err(|e| e.to_string()));
+            match parsed {
+                Ok(mut trigger) => {
+                    if trigger.name.is_empty() {
+                        trigger.name = file.file_stem().unwrap().to_string_lossy().to_string();
+                    }
+                    triggers.push(trigger);
+                }
+                Err(e) => eprintln!("{}", format!("{}: {}", file.display(), e).yellow()),
+            }
+        }
+        triggers
+    }
+
+    /// Whether `operation` on a package with `files` sets this trigger off.
+    pub fn matches(&self, operation: &str, files: &[String]) -> bool {
+        if !self.operations.is_empty() && !self.operations.iter().any(|o| o == operation) {
+            return false;
+        }
+        let patterns: Vec<Pattern> = self
+            .paths
+            .iter()
+            .filter_map(|p| Pattern::new(p.trim_start_matches('/')).ok())
+            .collect();
+        files.iter().any(|f| {
+            patterns
+                .iter()
+                .any(|p| p.matches(f.trim_start_matches('/')))
+        })
+    }
+
+    pub fn run(&self) -> Result<(), String> {
+        if let Some(description) = &self.description {
+            println!("{}", description);
+        }
+        run_script(&self.name, &self.commands, &[])
+    }
+}
+
+// Run `commands` with `/bin/sh` from the root of the target system,
+// chrooted into ROOT_DIR unless that is `/`.
+fn run_script(name: &str, commands: &[String], args: &[String]) -> Result<(), String> {
+    let cmd = if ROOT_DIR.as_path() == Path::new("/") {
+        Exec::cmd("/bin/sh")
+    } else {
+        Exec::cmd("chroot").arg(ROOT_DIR.as_os_str()).arg("/bin/sh")
+    };
+    let status = cmd
+        .arg("-c")
+        .arg(commands.join("\n"))
+        .arg(name)
+        .args(args)
+        .cwd("/")
+        .join()
+        .map_err(|e| format!("{}: {}", name, e))?;
+    match status {
+        ExitStatus::Exited(0) => Ok(()),
+        ExitStatus::Exited(code) => Err(format!("{} exited with code: {}", name, code)),
+        ExitStatus::Signaled(sig) => Err(format!("{} was killed by signal: {}", name, sig)),
+        other => Err(format!("{} failed: {:?}", name, other)),
+    }
+}
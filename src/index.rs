@@ -0,0 +1,453 @@
+use crate::{
+    dependency_names, transaction::suffixed, Application, BinRepo, PkgVersion, LOCAL_DIR,
+    LOCAL_INDEX,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{BufReader, BufWriter, Error, ErrorKind, Read, Result, Write},
+    os::unix::fs::FileExt,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+// Binary form of a package database, so a run only decodes the packages it
+// looks at instead of a whole YAML file.
+//
+//   "PIDX" | format u32 | header length u64 | header | record ...
+//
+// The header is a CBOR `Header` with one entry per package, sorted by name,
+// holding what lookups and searches need plus where the full CBOR
+// `Application` record starts, counted from the end of the header. Older
+// versions a repo keeps for downgrades have entries of their own in
+// `archive`, by name and then version.
+const MAGIC: &[u8; 4] = b"PIDX";
+const FORMAT: u32 = 1;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct Header {
+    date: SystemTime,
+    entries: Vec<IndexEntry>,
+    #[serde(default)]
+    archive: Vec<IndexEntry>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct IndexEntry {
+    pub name: String,
+    pub version: String,
+    pub description: Option<String>,
+    // names only, versions are checked on the full record
+    pub provides: Vec<String>,
+    pub replaces: Vec<String>,
+    offset: u64,
+    length: u64,
+}
+
+// Keeps the file open, so swapping in a new index under the same name does
+// not disturb readers of the old one.
+#[derive(Clone, Debug)]
+pub struct Index {
+    path: PathBuf,
+    file: Arc<File>,
+    pub date: SystemTime,
+    entries: Vec<IndexEntry>,
+    archive: Vec<IndexEntry>,
+    records_start: u64,
+}
+
+impl Index {
+    /// Read the header of an index, records stay on disk until asked for.
+    pub fn open(path: &Path) -> Result<Self> {
+        let handle = File::open(path)?;
+        let mut file = BufReader::new(&handle);
+        let mut magic = [0u8; 4];
+        let mut format = [0u8; 4];
+        let mut length = [0u8; 8];
+        file.read_exact(&mut magic)?;
+        file.read_exact(&mut format)?;
+        file.read_exact(&mut length)?;
+        if &magic != MAGIC {
+            return Err(invalid(format!("{}: not a package index", path.display())));
+        }
+        if u32::from_le_bytes(format) != FORMAT {
+            return Err(invalid(format!(
+                "{}: unsupported index format {}",
+                path.display(),
+                u32::from_le_bytes(format)
+            )));
+        }
+        let length = u64::from_le_bytes(length);
+        let header: Header = serde_cbor::from_reader((&mut file).take(length)).map_err(cbor)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: Arc::new(handle),
+            date: header.date,
+            entries: header.entries,
+            archive: header.archive,
+            records_start: 16 + length,
+        })
+    }
+
+    /// Write `apps` as an index at `path`, replacing the old one in a
+    /// single rename.
+    pub fn write<'a, I>(path: &Path, date: SystemTime, apps: I) -> Result<()>
+    where
+        I: IntoIterator<Item = &'a Application>,
+    {
+        Self::write_with_archive(path, date, apps, Vec::new())
+    }
+
+    fn write_with_archive<'a, I>(
+        path: &Path,
+        date: SystemTime,
+        apps: I,
+        mut archived: Vec<&Application>,
+    ) -> Result<()>
+    where
+        I: IntoIterator<Item = &'a Application>,
+    {
+        let mut apps: Vec<&Application> = apps.into_iter().collect();
+        apps.sort_by(|a, b| a.metadata.name.cmp(&b.metadata.name));
+        apps.dedup_by(|a, b| a.metadata.name == b.metadata.name);
+        archived.sort_by(|a, b| {
+            (&a.metadata.name, PkgVersion::of(&a.metadata))
+                .cmp(&(&b.metadata.name, PkgVersion::of(&b.metadata)))
+        });
+
+        let mut records: Vec<u8> = Vec::new();
+        let mut entries: Vec<IndexEntry> = Vec::new();
+        let mut archive: Vec<IndexEntry> = Vec::new();
+        for app in apps.into_iter() {
+            entries.push(record(&mut records, app)?);
+        }
+        for app in archived.into_iter() {
+            archive.push(record(&mut records, app)?);
+        }
+        let header = serde_cbor::to_vec(&Header {
+            date,
+            entries,
+            archive,
+        })
+        .map_err(cbor)?;
+
+        let tmp = suffixed(path, ".part");
+        {
+            let mut file = BufWriter::new(File::create(&tmp)?);
+            file.write_all(MAGIC)?;
+            file.write_all(&FORMAT.to_le_bytes())?;
+            file.write_all(&(header.len() as u64).to_le_bytes())?;
+            file.write_all(&header)?;
+            file.write_all(&records)?;
+            file.flush()?;
+        }
+        fs::rename(tmp, path)
+    }
+
+    /// Index a YAML repo database, e.g. a freshly synced `<repo>.db`.
+    pub fn import(repo: &BinRepo, path: &Path) -> Result<Self> {
+        Self::write_with_archive(
+            path,
+            repo.date,
+            repo.applications.values(),
+            repo.archive.values().flatten().collect(),
+        )?;
+        Self::open(path)
+    }
+
+    /// The whole index as a YAML repo database.
+    pub fn export(&self) -> Result<BinRepo> {
+        let mut archive: HashMap<String, Vec<Application>> = HashMap::new();
+        for e in self.archive.iter() {
+            archive
+                .entry(e.name.clone())
+                .or_default()
+                .push(self.read(e)?);
+        }
+        Ok(BinRepo {
+            applications: self
+                .all()?
+                .into_iter()
+                .map(|a| (a.metadata.name.clone(), a))
+                .collect::<HashMap<String, Application>>(),
+            date: self.date,
+            archive,
+        })
+    }
+
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.entry(name).is_some()
+    }
+
+    pub fn entry(&self, name: &str) -> Option<&IndexEntry> {
+        self.entries
+            .binary_search_by(|e| e.name.as_str().cmp(name))
+            .ok()
+            .map(|pos| &self.entries[pos])
+    }
+
+    pub fn get(&self, name: &str) -> Option<Application> {
+        let entry = self.entry(name)?;
+        self.read(entry).ok()
+    }
+
+    /// Every version of `name` the repo has, oldest first.
+    pub fn versions(&self, name: &str) -> Vec<Application> {
+        self.archive
+            .iter()
+            .filter(|e| e.name == name)
+            .chain(self.entry(name))
+            .filter_map(|e| self.read(e).ok())
+            .collect()
+    }
+
+    /// Packages named `name` or listing it in `provides`.
+    pub fn providers(&self, name: &str) -> Vec<Application> {
+        self.entries
+            .iter()
+            .filter(|e| e.name == name || e.provides.iter().any(|p| p == name))
+            .filter_map(|e| self.read(e).ok())
+            .collect()
+    }
+
+    /// Packages taking over from `name` when it was renamed.
+    pub fn replacing(&self, name: &str) -> Vec<Application> {
+        self.entries
+            .iter()
+            .filter(|e| e.replaces.iter().any(|r| r == name))
+            .filter_map(|e| self.read(e).ok())
+            .collect()
+    }
+
+    /// Decode every record, in name order.
+    pub fn all(&self) -> Result<Vec<Application>> {
+        let size = self
+            .file
+            .metadata()?
+            .len()
+            .saturating_sub(self.records_start);
+        let mut records = vec![0u8; size as usize];
+        self.file.read_exact_at(&mut records, self.records_start)?;
+        let mut apps: Vec<Application> = Vec::with_capacity(self.entries.len());
+        for e in self.entries.iter() {
+            let start = e.offset as usize;
+            let end = start + e.length as usize;
+            match records.get(start..end) {
+                Some(bytes) => apps.push(serde_cbor::from_slice(bytes).map_err(cbor)?),
+                None => return Err(invalid(format!("{}: truncated", self.path.display()))),
+            }
+        }
+        Ok(apps)
+    }
+
+    fn read(&self, entry: &IndexEntry) -> Result<Application> {
+        let mut bytes = vec![0u8; entry.length as usize];
+        self.file
+            .read_exact_at(&mut bytes, self.records_start + entry.offset)?;
+        serde_cbor::from_slice(&bytes).map_err(cbor)
+    }
+}
+
+// Append the CBOR record of `app` to `records` and describe it.
+fn record(records: &mut Vec<u8>, app: &Application) -> Result<IndexEntry> {
+    let offset = records.len() as u64;
+    serde_cbor::to_writer(&mut *records, app).map_err(cbor)?;
+    Ok(IndexEntry {
+        name: app.metadata.name.clone(),
+        version: app.metadata.full_version(),
+        description: app.metadata.description.clone(),
+        provides: dependency_names(&app.provides()),
+        replaces: dependency_names(&app.replaces()),
+        offset,
+        length: records.len() as u64 - offset,
+    })
+}
+
+/// Every installed package, in name order.
+pub fn installed() -> Vec<Application> {
+    match local_index() {
+        Ok(index) => index.all().unwrap_or_else(|_| read_manifests()),
+        Err(_) => read_manifests(),
+    }
+}
+
+/// The index of installed packages. It is written by every transaction and
+/// rebuilt from the manifests in LOCAL_DIR when missing or unreadable.
+pub fn local_index() -> Result<Index> {
+    match Index::open(LOCAL_INDEX.as_path()) {
+        Ok(index) => Ok(index),
+        Err(_) => rebuild_local(),
+    }
+}
+
+pub fn rebuild_local() -> Result<Index> {
+    let apps = read_manifests();
+    Index::write(LOCAL_INDEX.as_path(), SystemTime::now(), apps.iter())?;
+    Index::open(LOCAL_INDEX.as_path())
+}
+
+fn read_manifests() -> Vec<Application> {
+    let mut apps: Vec<Application> = Vec::new();
+    let mut dirs: Vec<PathBuf> = match fs::read_dir(LOCAL_DIR.as_path()) {
+        Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+        Err(_) => return apps,
+    };
+    dirs.sort();
+    for dir in dirs.iter() {
+        if let Ok(file) = File::open(dir.join("manifest.yml")) {
+            if let Ok(app) = serde_yaml::from_reader::<_, Application>(file) {
+                apps.push(app);
+            }
+        }
+    }
+    apps
+}
+
+fn invalid(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+fn cbor(e: serde_cbor::Error) -> Error {
+    Error::new(ErrorKind::InvalidData, e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{app, temp_path, with};
+    use std::{convert::TryInto, time::Duration};
+
+    fn names(apps: &[Application]) -> Vec<(String, String)> {
+        apps.iter()
+            .map(|a| (a.metadata.name.clone(), a.metadata.full_version()))
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = temp_path("round-trip.idx");
+        let date = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let apps = [
+            with(app("zsh", "5.8", &[]), "provides", &["sh"]),
+            with(app("bash", "5.1", &[]), "provides", &["sh=5.1"]),
+            app("bash", "4.4", &[]),
+        ];
+        Index::write(&path, date, apps.iter()).unwrap();
+
+        let mut head = [0u8; 16];
+        File::open(&path).unwrap().read_exact(&mut head).unwrap();
+        assert_eq!(&head[..4], MAGIC);
+        assert_eq!(
+            u32::from_le_bytes([head[4], head[5], head[6], head[7]]),
+            FORMAT
+        );
+
+        let index = Index::open(&path).unwrap();
+        assert_eq!(index.date, date);
+        // sorted by name, the first of a name is kept
+        assert_eq!(
+            index
+                .entries()
+                .iter()
+                .map(|e| (e.name.as_str(), e.version.as_str()))
+                .collect::<Vec<_>>(),
+            vec![("bash", "5.1-1"), ("zsh", "5.8-1")]
+        );
+        assert_eq!(index.entry("bash").unwrap().provides, vec!["sh"]);
+        let bash = index.get("bash").unwrap();
+        assert_eq!(
+            bash.metadata.description.as_deref(),
+            Some("the bash package")
+        );
+        assert_eq!(bash.provides(), vec!["sh=5.1"]);
+        assert!(index.get("dash").is_none());
+        assert_eq!(
+            names(&index.all().unwrap()),
+            vec![
+                ("bash".to_string(), "5.1-1".to_string()),
+                ("zsh".to_string(), "5.8-1".to_string())
+            ]
+        );
+        assert_eq!(names(&index.providers("sh")).len(), 2);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn keeps_archived_versions() {
+        let path = temp_path("archive.idx");
+        let mut repo = BinRepo {
+            applications: HashMap::new(),
+            date: SystemTime::UNIX_EPOCH,
+            archive: HashMap::new(),
+        };
+        repo.applications
+            .insert("bash".to_string(), app("bash", "5.1", &[]));
+        repo.archive.insert(
+            "bash".to_string(),
+            vec![app("bash", "5.0", &[]), app("bash", "4.4", &[])],
+        );
+        let index = Index::import(&repo, &path).unwrap();
+        assert_eq!(
+            names(&index.versions("bash"))
+                .into_iter()
+                .map(|(_, v)| v)
+                .collect::<Vec<_>>(),
+            vec!["4.4-1", "5.0-1", "5.1-1"]
+        );
+        assert_eq!(index.export().unwrap().archive["bash"].len(), 2);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let path = temp_path("magic.idx");
+        fs::write(&path, b"PK\x03\x04not an index at all").unwrap();
+        let err = Index::open(&path).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(err.to_string().ends_with("not a package index"));
+
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&(FORMAT + 1).to_le_bytes());
+        data.extend_from_slice(&0u64.to_le_bytes());
+        fs::write(&path, &data).unwrap();
+        let err = Index::open(&path).unwrap_err();
+        assert!(err.to_string().ends_with("unsupported index format 2"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let path = temp_path("truncated.idx");
+        let apps = [app("bash", "5.1", &[]), app("zsh", "5.8", &[])];
+        Index::write(&path, SystemTime::UNIX_EPOCH, apps.iter()).unwrap();
+        let data = fs::read(&path).unwrap();
+        let header = u64::from_le_bytes(data[8..16].try_into().unwrap()) as usize;
+
+        // cut into the header
+        for len in [3, 12, 16 + header / 2] {
+            fs::write(&path, &data[..len]).unwrap();
+            assert!(Index::open(&path).is_err(), "cut at {}", len);
+        }
+
+        // cut into the records, the header still reads
+        fs::write(&path, &data[..data.len() - 4]).unwrap();
+        let index = Index::open(&path).unwrap();
+        assert!(index.all().is_err());
+        assert!(index.get("zsh").is_none());
+        fs::remove_file(&path).unwrap();
+    }
+}
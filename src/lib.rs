@@ -2,33 +2,63 @@ pub mod application;
 pub mod architecture;
 pub mod bin_database;
 pub mod build_file;
+pub mod cache;
+pub mod check;
 pub mod config;
+pub mod constraint;
 pub mod dependency;
 pub mod deployment;
 pub mod function;
 pub mod help;
+pub mod hooks;
+pub mod index;
 pub mod license;
+pub mod lock;
 pub mod metadata;
+pub mod query;
+pub mod resolver;
 pub mod security;
+pub mod signature;
 pub mod source;
 pub mod source_database;
 pub mod statics;
+pub mod sync;
+#[cfg(test)]
+mod testing;
+pub mod transaction;
 pub mod utils;
+pub mod version;
 
-pub use application::Application;
+pub use application::{Application, FileEntry, FileKind, InstallReason};
 pub use architecture::Architecture;
 pub use bin_database::BinDatabase;
 pub use bin_database::BinRepo;
+pub use bin_database::SearchHit;
 pub use build_file::BuildFile;
-pub use config::Configuration;
+pub use cache::{cache_path, cached, CachedPackage};
+pub use check::{check_package, Problem};
+pub use config::{Configuration, RepoMeta, SigLevel};
+pub use constraint::{dependency_names, Constraint};
 pub use dependency::Dependency;
 pub use deployment::Deployment;
 pub use function::Function;
 pub use help::help;
+pub use hooks::{Hooks, Trigger};
+pub use index::{installed, local_index, rebuild_local, Index, IndexEntry};
 pub use license::License;
+pub use lock::Lock;
 pub use metadata::Metadata;
+pub use query::{
+    orphans, owners, pending_configs, print_config_diff, print_info, print_list, print_search,
+    relative_to_root, required_by,
+};
+pub use resolver::{Plan, Resolver, Step};
 pub use security::Security;
+pub use signature::{verify_download, Keyring, PublicKey, SecretKey};
 pub use source::Source;
 pub use source_database::SourceDatabase;
 pub use source_database::SourceRepo;
 pub use statics::*;
+pub use sync::{db_address, db_path, index_path, open_repo, sync_repo, SyncStatus};
+pub use transaction::Transaction;
+pub use version::{vercmp, PkgVersion};
@@ -0,0 +1,63 @@
+use crate::LOCK_FILE;
+use std::{
+    fs::{self, File, OpenOptions, TryLockError},
+    io::{Error, ErrorKind, Result, Write},
+    path::Path,
+    process,
+};
+
+// An advisory lock: flock(2) on a file that also records the PID of its
+// holder for others to report. The kernel drops it with the process that
+// holds it, so a lock is never left behind and the file itself stays.
+#[derive(Debug)]
+pub struct Lock {
+    file: File,
+}
+
+impl Lock {
+    /// Take the lock on the package database, LIB_DIR/db.lck.
+    pub fn database() -> Result<Self> {
+        Self::acquire(LOCK_FILE.as_path())
+    }
+
+    pub fn acquire(path: &Path) -> Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        match file.try_lock() {
+            Ok(_) => {}
+            Err(TryLockError::WouldBlock) => {
+                let holder = match owner(path) {
+                    Some(pid) => format!("process {}", pid),
+                    None => String::from("another process"),
+                };
+                return Err(Error::new(
+                    ErrorKind::WouldBlock,
+                    format!(
+                        "{} is held by {}, wait for it to finish",
+                        path.display(),
+                        holder
+                    ),
+                ));
+            }
+            Err(TryLockError::Error(e)) => return Err(e),
+        }
+        file.set_len(0)?;
+        writeln!(file, "{}", process::id())?;
+        Ok(Self { file })
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        // the lock itself goes with the file descriptor
+        self.file.set_len(0).ok();
+    }
+}
+
+fn owner(path: &Path) -> Option<u32> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
@@ -11,44 +11,72 @@ mod application;
 mod architecture;
 mod bin_database;
 mod build_file;
+mod cache;
+mod check;
 mod config;
+mod constraint;
 mod dependency;
 mod deployment;
 mod function;
 mod help;
+mod hooks;
+mod index;
 mod license;
+mod lock;
 mod metadata;
+mod query;
+mod resolver;
 mod security;
+mod signature;
 mod source;
 mod source_database;
 mod statics;
+mod sync;
+#[cfg(test)]
+mod testing;
+mod transaction;
+mod version;
 // Local
-pub use application::Application;
+pub use application::{Application, FileEntry, FileKind, InstallReason};
 pub use architecture::Architecture;
-pub use bin_database::{BinDatabase, BinRepo};
+pub use bin_database::{BinDatabase, BinRepo, SearchHit};
 pub use build_file::BuildFile;
-pub use config::{Configuration, RepoMeta};
+pub use cache::{cache_path, cached, CachedPackage};
+pub use check::{check_package, Problem};
+pub use config::{Configuration, RepoMeta, SigLevel};
+pub use constraint::{dependency_names, Constraint};
 pub use dependency::Dependency;
 pub use deployment::Deployment;
 pub use function::Function;
 pub use help::help;
+pub use hooks::{Hooks, Trigger};
+pub use index::{installed, local_index, rebuild_local, Index, IndexEntry};
 pub use license::License;
+pub use lock::Lock;
 pub use metadata::Metadata;
+pub use query::{
+    orphans, owners, pending_configs, print_config_diff, print_info, print_list, print_search,
+    relative_to_root,
+};
+pub use resolver::{Plan, Resolver, Step};
 pub use security::Security;
+pub use signature::{verify_download, Keyring, SecretKey};
 pub use source::Source;
 pub use source_database::SourceDatabase;
 pub use statics::*;
+pub use sync::{db_address, db_path, index_path, open_repo, sync_repo, SyncStatus};
+pub use transaction::Transaction;
 pub use utils::{download_http, prepare_bases};
+pub use version::{vercmp, PkgVersion};
 
 // External
 use colored::Colorize;
-use solvent::DepGraph;
+use indicatif::HumanBytes;
 use std::{
     env,
-    fs::{remove_dir_all, File},
+    fs::{remove_dir_all, remove_file, File},
     path::PathBuf,
 };
-use url::Url;
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
     // prepare directories
@@ -66,17 +94,31 @@ async fn main() -> std::io::Result<()> {
         CONF_DIR.to_path_buf(),
     ])
     .unwrap();
+    // commands that change the system hold the database lock until they are
+    // done, the others only read and don't take it
+    let lock = if takes_lock(&env::args().skip(1).collect::<Vec<String>>()) {
+        let lock = match Lock::database() {
+            Ok(lock) => lock,
+            Err(e) => {
+                eprintln!("{}", e.to_string().red());
+                std::process::exit(1);
+            }
+        };
+        // undo a transaction that was interrupted half way
+        if let Err(e) = Transaction::recover() {
+            eprintln!("{}", e.to_string().red());
+            std::process::exit(1);
+        }
+        Some(lock)
+    } else {
+        None
+    };
     // preper config file if running for the first time
     if !CONF_FILE.as_path().exists() {
         let mut file = File::create(CONF_FILE.as_path()).unwrap();
         serde_yaml::to_writer(&mut file, &Configuration::gen()).unwrap()
     }
 
-    // dependencies graph
-    let mut run_depgraph: DepGraph<String> = DepGraph::new();
-    let mut opt_depgraph: DepGraph<String> = DepGraph::new();
-    let mut buid_depgraph: DepGraph<String> = DepGraph::new();
-    let mut test_depgraph: DepGraph<String> = DepGraph::new();
     // local database
     let mut db: BinDatabase = BinDatabase::new();
     // read the config
@@ -85,70 +127,50 @@ async fn main() -> std::io::Result<()> {
     // let database
     // check if all repo listed in config file existed or download it
     println!("{}", "PREPARING DATABASE".green());
+    let keyring = Keyring::load();
+    // repos that can't be fetched or read are left out, so that e.g. the
+    // key to verify them can still be added; commands that look packages up
+    // in repos warn about them
+    let mut unavailable: Vec<String> = Vec::new();
+    // readers fetch a repo that was never synced under the lock as well
+    let mut fetch_lock: Option<Lock> = None;
     for repo in repo_config.repos.iter() {
-        let db_file_path = SYNC_DIR.join(format!("{}.db", &repo.name));
-        let static_url = Url::parse(&repo.static_address).unwrap();
-        let db_addres = static_url.join(&repo.name).unwrap();
-
-        let address = format!("{}.db", &db_addres.to_string());
+        let db_file_path = db_path(&repo.name);
         if !db_file_path.exists() {
-            download_http(
-                db_file_path.to_str().unwrap(),
-                &format!("{}.db", &repo.name),
-                &address,
-            )
-            .await
-            .unwrap();
-        }
-        let repo_file = File::open(db_file_path).unwrap();
-        let repo_data: BinRepo = serde_yaml::from_reader(repo_file).unwrap();
-
-        db.repos.insert(repo.name.clone(), repo_data);
-    }
-
-    for (_, repo) in db.repos.iter() {
-        for (_, app) in repo.applications.iter() {
-            if let Some(deps) = &app.dependencies {
-                // runtime dependencies
-                if let Some(rd) = &deps.run_dependencies {
-                    if rd.is_empty() {
-                        run_depgraph.register_node(app.metadata.name.clone());
-                    } else {
-                        run_depgraph.register_dependencies(app.metadata.name.clone(), rd.to_vec())
-                    }
-                }
-                // optional dependencies
-                if let Some(od) = &deps.opt_dependencies {
-                    if od.is_empty() {
-                        opt_depgraph.register_node(app.metadata.name.clone());
-                    } else {
-                        opt_depgraph.register_dependencies(app.metadata.name.clone(), od.to_vec())
+            if lock.is_none() && fetch_lock.is_none() {
+                match Lock::database() {
+                    Ok(l) => fetch_lock = Some(l),
+                    Err(e) => {
+                        unavailable.push(format!(
+                            "{} is not synced yet, run pi sync ({})",
+                            repo.name.bold(),
+                            e
+                        ));
+                        continue;
                     }
                 }
-                // build dependencies
-                if let Some(bd) = &deps.build_dependencies {
-                    if bd.is_empty() {
-                        buid_depgraph.register_node(app.metadata.name.clone());
-                    } else {
-                        buid_depgraph.register_dependencies(app.metadata.name.clone(), bd.to_vec())
-                    }
-                }
-                // test dependencies
-                if let Some(td) = &deps.test_dependencies {
-                    if td.is_empty() {
-                        test_depgraph.register_node(app.metadata.name.clone());
-                    } else {
-                        test_depgraph.register_dependencies(app.metadata.name.clone(), td.to_vec())
-                    }
+            }
+            match sync_repo(repo, &keyring, true).await {
+                SyncStatus::Updated(data) => {
+                    db.repos.insert(repo.name.clone(), data);
                 }
-            } else {
-                run_depgraph.register_node(app.metadata.name.clone());
-                opt_depgraph.register_node(app.metadata.name.clone());
-                buid_depgraph.register_node(app.metadata.name.clone());
-                test_depgraph.register_node(app.metadata.name.clone());
+                status => unavailable.push(format!("{} {}", repo.name.bold(), status)),
             }
+            continue;
+        }
+        match open_repo(&repo.name) {
+            Ok(index) => {
+                db.repos.insert(repo.name.clone(), index);
+            }
+            Err(e) => unavailable.push(format!("{} {}", repo.name.bold(), e)),
         }
     }
+    drop(fetch_lock);
+    let warn_unavailable = || {
+        unavailable
+            .iter()
+            .for_each(|u| eprintln!("{} {}", "warning: repo not available:".yellow(), u));
+    };
 
     let args: Vec<String> = env::args_os()
         .map(|a| a.to_str().unwrap().to_string())
@@ -164,6 +186,7 @@ async fn main() -> std::io::Result<()> {
 
         match verb.as_ref() {
             "b" | "build" | "-b" | "--build" => {
+                warn_unavailable();
                 if !PKG_DIR.to_path_buf().read_dir()?.next().is_none() {
                     remove_dir_all(PKG_DIR.to_path_buf()).unwrap();
                     prepare_bases(vec![PKG_DIR.to_path_buf()]).unwrap();
@@ -179,43 +202,103 @@ async fn main() -> std::io::Result<()> {
                             for package in files.iter() {
                                 let target_package: BuildFile =
                                     BuildFile::from_file(package.to_path_buf()).unwrap();
-                                target_package
-                                    .build_all(&run_depgraph, &repo_config, &db)
-                                    .await;
+                                target_package.build_all(&repo_config, &db).await;
                             }
                         }
                         _ => {
                             let target_package: BuildFile =
                                 BuildFile::from_file(PKG_FILE.to_path_buf()).unwrap();
-                            target_package
-                                .build_all(&run_depgraph, &repo_config, &db)
-                                .await;
+                            target_package.build_all(&repo_config, &db).await;
                         }
                     }
                 } else {
                     help("build");
                 }
             }
+            "c" | "check" | "-c" | "--check" => {
+                let apps: Vec<Application> = match packages {
+                    Some(pkgs) => pkgs
+                        .iter()
+                        .map(|p| match Application::is_installed(p) {
+                            Some(app) => app,
+                            None => {
+                                eprintln!("{} is not installed", p.red().bold());
+                                std::process::exit(1);
+                            }
+                        })
+                        .collect(),
+                    None => db.list_installed().unwrap_or_default(),
+                };
+                let mut broken = false;
+                for app in apps.iter() {
+                    let problems = check_package(app);
+                    problems
+                        .iter()
+                        .for_each(|p| println!("{}\t{}", app.metadata.name, p));
+                    let summary = format!(
+                        "{}: {} files, {} problems",
+                        app.metadata.name,
+                        app.files.len(),
+                        problems.len()
+                    );
+                    if problems.is_empty() {
+                        eprintln!("{}", summary.green());
+                    } else {
+                        eprintln!("{}", summary.red());
+                        broken = true;
+                    }
+                }
+                if broken {
+                    std::process::exit(1);
+                }
+            }
+            "diff-configs" | "--diff-configs" => {
+                let list_only = packages.unwrap_or_default().iter().any(|a| a == "--list");
+                let pending = pending_configs(&db.list_installed().unwrap_or_default());
+                if pending.is_empty() {
+                    println!("{}", "No config files to merge.".green());
+                }
+                for (name, target) in pending.iter() {
+                    println!("{} {}", name.bold(), target.display());
+                    if !list_only {
+                        print_config_diff(target);
+                        println!();
+                    }
+                }
+            }
             "g" | "generate" | "-g" | "--generate" => {
                 let bf = BuildFile::new();
                 let file = File::create("pkgbuild.yml").unwrap();
                 serde_yaml::to_writer(file, &bf).unwrap();
             }
             "i" | "install" | "-i" | "--install" => {
-                if let Some(pkgs) = packages {
+                if let Some(mut pkgs) = packages {
+                    let overwrite = take_option(&mut pkgs, "--overwrite");
+                    if pkgs.is_empty() {
+                        help("install");
+                        return Ok(());
+                    }
                     let local: String = pkgs[0].clone();
                     match local.as_ref() {
                         "-f" | "--file" => {
                             let files: Vec<PathBuf> =
                                 pkgs.iter().skip(1).map(|p| PathBuf::from(p)).collect();
-                            db.install_files(&run_depgraph, &repo_config, files.to_vec())
+                            if let Err(errors) = db
+                                .install_files(&repo_config, files.to_vec(), &overwrite)
                                 .await
-                                .unwrap();
+                            {
+                                errors.iter().for_each(|e| eprintln!("{}", e.red()));
+                                std::process::exit(1);
+                            }
                         }
                         _ => {
-                            db.install(&run_depgraph, &repo_config, pkgs.to_vec())
-                                .await
-                                .unwrap();
+                            warn_unavailable();
+                            if let Err(errors) =
+                                db.install(&repo_config, pkgs.to_vec(), &overwrite).await
+                            {
+                                errors.iter().for_each(|e| eprintln!("{}", e.red()));
+                                std::process::exit(1);
+                            }
                         }
                     }
                 } else {
@@ -223,51 +306,305 @@ async fn main() -> std::io::Result<()> {
                 }
             }
             "r" | "remove" | "-r" | "--remove" => {
+                if let Some(mut pkgs) = packages {
+                    let cascade = take_flag(&mut pkgs, "--cascade");
+                    let recursive = take_flag(&mut pkgs, "--recursive");
+                    if pkgs.is_empty() {
+                        help("remove");
+                        return Ok(());
+                    }
+                    if let Err(errors) = db.remove(&repo_config, &pkgs, cascade, recursive) {
+                        errors.iter().for_each(|e| eprintln!("{}", e.red()));
+                        std::process::exit(1);
+                    }
+                } else {
+                    help("remove");
+                }
+            }
+            "info" | "--info" => {
                 if let Some(pkgs) = packages {
-                    if !pkgs.is_empty() {
-                        for pkg in pkgs.iter() {
-                            if let Some(app) = Application::is_installed(&pkg) {
-                                let app_dir: PathBuf = LOCAL_DIR.to_owned().join(&pkg);
-                                let files = app.files.clone();
-                                files.iter().for_each(|file| {
-                                    let f: Vec<String> = file
-                                        .split(" ")
-                                        .into_iter()
-                                        .map(|a| a.to_string())
-                                        .collect();
-                                    let fi = f[0].clone();
-                                    std::fs::remove_file(ROOT_DIR.join(&fi)).unwrap()
-                                });
-
-                                std::fs::remove_dir_all(app_dir).unwrap();
+                    warn_unavailable();
+                    let installed = db.list_installed().unwrap_or_default();
+                    let remote = pkgs.iter().any(|p| p == "-r" || p == "--remote");
+                    let mut missing = false;
+                    for p in pkgs.iter().filter(|p| !p.starts_with('-')) {
+                        match (Application::is_installed(p), db.lookup(&repo_config, p)) {
+                            (Some(app), _) if !remote => print_info(&app, "local", &installed),
+                            (_, Some(target)) => {
+                                print_info(&target.package, &target.repo, &installed)
+                            }
+                            _ => {
+                                eprintln!("{} is not found", p.red().bold());
+                                missing = true;
                             }
                         }
-                    } else {
-                        help("remove");
+                    }
+                    if missing {
+                        std::process::exit(1);
                     }
                 } else {
-                    help("remove");
+                    help("query");
                 }
             }
+            "files" | "--files" => {
+                if let Some(pkgs) = packages {
+                    warn_unavailable();
+                    for p in pkgs.iter() {
+                        let app = match Application::is_installed(p) {
+                            Some(app) => app,
+                            None => match db.lookup(&repo_config, p) {
+                                Some(target) => target.package,
+                                None => {
+                                    eprintln!("{} is not found", p.red().bold());
+                                    std::process::exit(1);
+                                }
+                            },
+                        };
+                        for path in app.file_paths().iter() {
+                            println!("{} /{}", app.metadata.name.bold(), path);
+                        }
+                    }
+                } else {
+                    help("query");
+                }
+            }
+            "owns" | "--owns" => {
+                if let Some(paths) = packages {
+                    let installed = db.list_installed().unwrap_or_default();
+                    let mut orphan = false;
+                    for path in paths.iter() {
+                        let owners = owners(path, &installed);
+                        if owners.is_empty() {
+                            eprintln!("No package owns /{}", relative_to_root(path).red());
+                            orphan = true;
+                        }
+                        for app in owners.iter() {
+                            println!(
+                                "/{} is owned by {} {}-{}",
+                                relative_to_root(path),
+                                app.metadata.name.bold(),
+                                app.metadata.version,
+                                app.metadata.release
+                            );
+                        }
+                    }
+                    if orphan {
+                        std::process::exit(1);
+                    }
+                } else {
+                    help("query");
+                }
+            }
+            "autoremove" | "--autoremove" => {
+                if let Err(errors) = db.autoremove() {
+                    errors.iter().for_each(|e| eprintln!("{}", e.red()));
+                    std::process::exit(1);
+                }
+            }
+            "mark" | "--mark" => {
+                let args: Vec<String> = packages.unwrap_or_default();
+                let reason = match args.first().map(|a| a.as_str()) {
+                    Some("--explicit") => InstallReason::Explicit,
+                    Some("--dep") => InstallReason::Dependency,
+                    _ => {
+                        help("mark");
+                        return Ok(());
+                    }
+                };
+                for p in args.iter().skip(1) {
+                    match Application::set_install_reason(p, reason) {
+                        Ok(_) => println!("{} {}", p.bold(), reason),
+                        Err(e) => {
+                            eprintln!("{}", e.to_string().red());
+                            std::process::exit(1);
+                        }
+                    }
+                }
+            }
+            "hold" | "--hold" | "unhold" | "--unhold" => {
+                let args: Vec<String> = packages.unwrap_or_default();
+                if args.is_empty() {
+                    repo_config.hold.iter().for_each(|p| println!("{}", p));
+                    return Ok(());
+                }
+                let hold = !verb.ends_with("unhold");
+                let mut config = repo_config.clone();
+                for p in args.iter() {
+                    if hold && !config.is_held(p) {
+                        config.hold.push(p.clone());
+                    } else if !hold {
+                        config.hold.retain(|h| h != p);
+                    }
+                }
+                config.hold.sort();
+                if let Err(e) = config.save() {
+                    eprintln!("{}", e.to_string().red());
+                    std::process::exit(1);
+                }
+                let state = if hold { "held" } else { "not held" };
+                args.iter().for_each(|p| println!("{} {}", p.bold(), state));
+            }
+            "l" | "list" | "-l" | "--list" => print_list(&db.list_installed().unwrap_or_default()),
             "s" | "search" | "-s" | "--search" => {
                 if let Some(pkgs) = packages {
-                    if !pkgs.is_empty() {
-                        for p in pkgs.iter() {
-                            let res = db.find(&repo_config, p);
-                            if let Some(app) = res {
-                                println!("{:#?}", app);
-                            } else {
-                                println!("{} is not found", p.red().bold());
-                            }
+                    warn_unavailable();
+                    match db.search(&repo_config, &pkgs) {
+                        Ok(hits) if hits.is_empty() => {
+                            println!("{} is not found", pkgs.join(" ").red().bold());
+                            std::process::exit(1);
+                        }
+                        Ok(hits) => print_search(&hits, &db.list_installed().unwrap_or_default()),
+                        Err(e) => {
+                            eprintln!("{}", e.red());
+                            std::process::exit(1);
                         }
-                    } else {
-                        help("search");
                     }
                 } else {
                     help("search");
                 }
             }
-            "u" | "update" | "-u" | "--update" => db.update(&run_depgraph, &repo_config).await,
+            "db" | "--db" => {
+                let args: Vec<String> = packages.unwrap_or_default();
+                match args.first().map(|a| a.as_str()) {
+                    Some("rebuild") => {
+                        let mut errors: Vec<String> = Vec::new();
+                        match rebuild_local() {
+                            Ok(index) => println!("{} {} packages", "local".bold(), index.len()),
+                            Err(e) => errors.push(format!("local: {}", e)),
+                        }
+                        for repo in repo_config.repos.iter() {
+                            remove_file(index_path(&repo.name)).ok();
+                            match open_repo(&repo.name) {
+                                Ok(index) => {
+                                    println!("{} {} packages", repo.name.bold(), index.len())
+                                }
+                                Err(e) => errors.push(e),
+                            }
+                        }
+                        if !errors.is_empty() {
+                            errors.iter().for_each(|e| eprintln!("{}", e.red()));
+                            std::process::exit(1);
+                        }
+                    }
+                    Some("export") if args.len() == 2 => {
+                        let index = if args[1] == "local" {
+                            local_index().map_err(|e| e.to_string())
+                        } else {
+                            open_repo(&args[1])
+                        };
+                        match index.and_then(|i| i.export().map_err(|e| e.to_string())) {
+                            Ok(data) => serde_yaml::to_writer(std::io::stdout(), &data).unwrap(),
+                            Err(e) => {
+                                eprintln!("{}", e.red());
+                                std::process::exit(1);
+                            }
+                        }
+                    }
+                    _ => help("db"),
+                }
+            }
+            "cache" | "--cache" => {
+                let mut args: Vec<String> = packages.unwrap_or_default();
+                let installed_only = take_flag(&mut args, "--installed");
+                let keep = take_option(&mut args, "--keep").pop();
+                match args.first().map(|a| a.as_str()) {
+                    Some("list") => {
+                        let installed = db.list_installed().unwrap_or_default();
+                        let packages = cache::list();
+                        for p in packages.iter() {
+                            let mark = if p.is_installed(&installed) {
+                                " [installed]".green().to_string()
+                            } else {
+                                String::new()
+                            };
+                            println!(
+                                "{} {} {}{}",
+                                p.name.bold(),
+                                p.version,
+                                HumanBytes(p.size),
+                                mark
+                            );
+                        }
+                        let total: u64 = packages.iter().map(|p| p.size).sum();
+                        println!("{} packages, {}", packages.len(), HumanBytes(total));
+                    }
+                    Some("clean") => {
+                        let keep = match keep.map(|k| k.parse::<usize>()) {
+                            Some(Ok(k)) => k,
+                            Some(Err(_)) => {
+                                help("cache");
+                                std::process::exit(1);
+                            }
+                            None => 3,
+                        };
+                        let installed = db.list_installed().unwrap_or_default();
+                        match cache::clean(keep, installed_only, &installed) {
+                            Ok((removed, freed)) => {
+                                removed
+                                    .iter()
+                                    .for_each(|f| println!("removed {}", f.display()));
+                                println!("{} {}", "freed".green(), HumanBytes(freed));
+                            }
+                            Err(e) => {
+                                eprintln!("{}", e.to_string().red());
+                                std::process::exit(1);
+                            }
+                        }
+                    }
+                    _ => help("cache"),
+                }
+            }
+            "k" | "key" | "-k" | "--key" => {
+                let args: Vec<String> = packages.unwrap_or_default();
+                match args.first().map(|a| a.as_str()) {
+                    Some("add") if args.len() > 1 => {
+                        for file in args.iter().skip(1) {
+                            match Keyring::add(&PathBuf::from(file)) {
+                                Ok(key) => println!("{} {}", "added key".green(), key.id_hex()),
+                                Err(e) => {
+                                    eprintln!("{}", e.red());
+                                    std::process::exit(1);
+                                }
+                            }
+                        }
+                    }
+                    Some("remove") if args.len() > 1 => {
+                        for id in args.iter().skip(1) {
+                            if let Err(e) = Keyring::remove(id) {
+                                eprintln!("{}", e.red());
+                                std::process::exit(1);
+                            }
+                        }
+                    }
+                    Some("list") => keyring.keys.iter().for_each(|k| println!("{}", k.id_hex())),
+                    _ => help("key"),
+                }
+            }
+            "y" | "sync" | "-y" | "--sync" => {
+                let mut args: Vec<String> = packages.unwrap_or_default();
+                let force = take_flag(&mut args, "--force");
+                if !db.sync(&repo_config, force).await {
+                    std::process::exit(1);
+                }
+            }
+            "U" | "upgrade" | "-U" | "--upgrade" => {
+                warn_unavailable();
+                if let Err(errors) = db.upgrade(&repo_config).await {
+                    errors.iter().for_each(|e| eprintln!("{}", e.red()));
+                    std::process::exit(1);
+                }
+            }
+            // sync, then upgrade
+            "u" | "update" | "-u" | "--update" => {
+                let synced = db.sync(&repo_config, false).await;
+                if let Err(errors) = db.upgrade(&repo_config).await {
+                    errors.iter().for_each(|e| eprintln!("{}", e.red()));
+                    std::process::exit(1);
+                }
+                if !synced {
+                    std::process::exit(1);
+                }
+            }
             _ => help("all"),
         }
     } else {
@@ -276,3 +613,69 @@ async fn main() -> std::io::Result<()> {
 
     Ok(())
 }
+
+fn takes_lock(args: &[String]) -> bool {
+    let verb = match args.first() {
+        Some(verb) => verb.as_str(),
+        None => return false,
+    };
+    // listing the cache only reads it
+    if matches!(verb, "cache" | "--cache") {
+        return args.get(1).is_some_and(|a| a == "clean");
+    }
+    // builds install missing build dependencies
+    matches!(
+        verb,
+        "b" | "build"
+            | "-b"
+            | "--build"
+            | "i"
+            | "install"
+            | "-i"
+            | "--install"
+            | "r"
+            | "remove"
+            | "-r"
+            | "--remove"
+            | "autoremove"
+            | "--autoremove"
+            | "mark"
+            | "--mark"
+            | "hold"
+            | "--hold"
+            | "unhold"
+            | "--unhold"
+            | "db"
+            | "--db"
+            | "y"
+            | "sync"
+            | "-y"
+            | "--sync"
+            | "U"
+            | "upgrade"
+            | "-U"
+            | "--upgrade"
+            | "u"
+            | "update"
+            | "-u"
+            | "--update"
+    )
+}
+
+// Pull every "<flag> <value>" pair out of the arguments.
+fn take_option(args: &mut Vec<String>, flag: &str) -> Vec<String> {
+    let mut values: Vec<String> = Vec::new();
+    while let Some(pos) = args.iter().position(|a| a == flag) {
+        args.remove(pos);
+        if pos < args.len() {
+            values.push(args.remove(pos));
+        }
+    }
+    values
+}
+
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    let before = args.len();
+    args.retain(|a| a != flag);
+    args.len() != before
+}
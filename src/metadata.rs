@@ -1,9 +1,12 @@
-use super::{Architecture, License};
+use super::{Architecture, License, PkgVersion};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct Metadata {
     pub name: String,
+    // bumped when the upstream versioning scheme goes backwards
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub epoch: u32,
     pub version: String,
     pub release: u32,
     pub description: Option<String>,
@@ -12,3 +15,14 @@ pub struct Metadata {
     pub project_url: Vec<String>,
     pub project_ownder: Vec<String>,
 }
+
+impl Metadata {
+    /// `[epoch:]version-release`
+    pub fn full_version(&self) -> String {
+        PkgVersion::of(self).to_string()
+    }
+}
+
+fn is_zero(n: &u32) -> bool {
+    *n == 0
+}
@@ -0,0 +1,211 @@
+use crate::{
+    bin_database::SearchHit, dependency_names, Application, InstallReason, PkgVersion, ROOT_DIR,
+};
+use colored::Colorize;
+use std::{
+    collections::HashSet,
+    env, fs,
+    path::{Path, PathBuf},
+};
+use text_diff::{diff, Difference};
+
+// Installed packages with a run dependency that `app` satisfies.
+pub fn required_by(app: &Application, installed: &[Application]) -> Vec<String> {
+    installed
+        .iter()
+        .filter(|other| other.metadata.name != app.metadata.name)
+        .filter(|other| {
+            dependency_names(&other.run_dependencies())
+                .iter()
+                .any(|dep| app.provides_name(dep))
+        })
+        .map(|other| other.metadata.name.clone())
+        .collect()
+}
+
+/// Installed dependencies that no explicitly installed package needs,
+/// directly or through other dependencies.
+pub fn orphans(installed: &[Application]) -> Vec<Application> {
+    let mut queue: Vec<&Application> = installed
+        .iter()
+        .filter(|a| Application::install_reason(&a.metadata.name) == InstallReason::Explicit)
+        .collect();
+    let mut needed: HashSet<String> = queue.iter().map(|a| a.metadata.name.clone()).collect();
+    while let Some(app) = queue.pop() {
+        for dep in dependency_names(&app.run_dependencies()).iter() {
+            for other in installed.iter().filter(|o| o.provides_name(dep)) {
+                if needed.insert(other.metadata.name.clone()) {
+                    queue.push(other);
+                }
+            }
+        }
+    }
+    installed
+        .iter()
+        .filter(|a| !needed.contains(&a.metadata.name))
+        .cloned()
+        .collect()
+}
+
+/// Installed packages listing `path` in their manifest. Relative paths are
+/// taken from the current directory, absolute ones from ROOT_DIR.
+pub fn owners<'a>(path: &str, installed: &'a [Application]) -> Vec<&'a Application> {
+    let target = relative_to_root(path);
+    installed
+        .iter()
+        .filter(|app| app.file_paths().iter().any(|f| f == &target))
+        .collect()
+}
+
+pub fn relative_to_root(path: &str) -> String {
+    let p = Path::new(path);
+    let absolute = if p.is_absolute() {
+        p.to_path_buf()
+    } else {
+        env::current_dir().unwrap().join(p)
+    };
+    let rel = absolute
+        .strip_prefix(ROOT_DIR.as_path())
+        .unwrap_or(&absolute);
+    rel.to_str()
+        .unwrap_or_default()
+        .trim_start_matches('/')
+        .to_string()
+}
+
+pub fn print_info(app: &Application, origin: &str, installed: &[Application]) {
+    let meta = &app.metadata;
+    let list = |items: Vec<String>| {
+        if items.is_empty() {
+            String::from("None")
+        } else {
+            items.join("  ")
+        }
+    };
+    let deps = app.dependencies.clone().unwrap_or_default();
+
+    field("Name", meta.name.bold().to_string());
+    field("Version", meta.full_version());
+    field("Description", meta.description.clone().unwrap_or_default());
+    field(
+        "Architecture",
+        list(
+            meta.architecture
+                .iter()
+                .map(|a| format!("{:?}", a).to_lowercase())
+                .collect(),
+        ),
+    );
+    field(
+        "Licenses",
+        list(meta.licenses.iter().map(|l| format!("{:?}", l)).collect()),
+    );
+    field("URL", list(meta.project_url.clone()));
+    field("Owners", list(meta.project_ownder.clone()));
+    field("Repository", origin.to_string());
+    field("Provides", list(app.provides()));
+    field("Depends On", list(app.run_dependencies()));
+    field(
+        "Optional Deps",
+        list(deps.opt_dependencies.unwrap_or_default()),
+    );
+    field("Conflicts With", list(app.conflicts()));
+    field("Replaces", list(app.replaces()));
+    if Application::is_installed(&meta.name).is_some() {
+        field("Required By", list(required_by(app, installed)));
+        field(
+            "Install Reason",
+            Application::install_reason(&meta.name).to_string(),
+        );
+    }
+    field("Files", app.files.len().to_string());
+    println!();
+}
+
+pub fn print_list(installed: &[Application]) {
+    for app in installed.iter() {
+        let reason = match Application::install_reason(&app.metadata.name) {
+            InstallReason::Explicit => "explicit".green(),
+            InstallReason::Dependency => "dependency".blue(),
+        };
+        println!(
+            "{} {} {}",
+            app.metadata.name.bold(),
+            app.metadata.full_version(),
+            reason
+        );
+    }
+}
+
+pub fn print_search(hits: &[SearchHit], installed: &[Application]) {
+    for hit in hits.iter() {
+        let meta = &hit.package.metadata;
+        let marker = match installed.iter().find(|a| a.metadata.name == meta.name) {
+            Some(a) if PkgVersion::of(&a.metadata) == PkgVersion::of(meta) => {
+                " [installed]".cyan().to_string()
+            }
+            Some(a) => format!(" [installed: {}]", a.metadata.full_version())
+                .cyan()
+                .to_string(),
+            None => String::new(),
+        };
+        println!(
+            "{}/{} {}{}",
+            hit.repo.magenta(),
+            meta.name.bold(),
+            meta.full_version().green(),
+            marker
+        );
+        if let Some(description) = &meta.description {
+            println!("    {}", description);
+        }
+    }
+}
+
+/// Config files of installed packages with a `.pinew` waiting next to them.
+pub fn pending_configs(installed: &[Application]) -> Vec<(String, PathBuf)> {
+    let mut pending: Vec<(String, PathBuf)> = Vec::new();
+    for app in installed.iter() {
+        for path in app.backup_files().iter() {
+            let target = ROOT_DIR.join(path);
+            let mut pinew = target.as_os_str().to_owned();
+            pinew.push(".pinew");
+            if Path::new(&pinew).exists() {
+                pending.push((app.metadata.name.clone(), target));
+            }
+        }
+    }
+    pending
+}
+
+pub fn print_config_diff(target: &Path) {
+    let mut pinew = target.as_os_str().to_owned();
+    pinew.push(".pinew");
+    let pinew = PathBuf::from(pinew);
+    let current = fs::read_to_string(target).unwrap_or_default();
+    let new = match fs::read_to_string(&pinew) {
+        Ok(text) => text,
+        Err(e) => {
+            println!("{}: {}", pinew.display(), e.to_string().red());
+            return;
+        }
+    };
+    println!("{}", format!("--- {}", target.display()).bold());
+    println!("{}", format!("+++ {}", pinew.display()).bold());
+    let (_, changes) = diff(&current, &new, "\n");
+    for change in changes.iter() {
+        match change {
+            Difference::Same(text) => text.lines().for_each(|l| println!(" {}", l)),
+            Difference::Rem(text) => text
+                .lines()
+                .for_each(|l| println!("{}", format!("-{}", l).red())),
+            Difference::Add(text) => text
+                .lines()
+                .for_each(|l| println!("{}", format!("+{}", l).green())),
+        }
+    }
+}
+
+fn field(label: &str, value: String) {
+    println!("{} : {}", format!("{:<15}", label).magenta(), value);
+}
@@ -0,0 +1,903 @@
+use crate::bin_database::TargetPackage;
+use crate::cache::{self, CACHE_REPO};
+use crate::{
+    dependency_names, Application, BinDatabase, Configuration, Constraint, InstallReason,
+    PkgVersion,
+};
+use colored::Colorize;
+use std::collections::HashSet;
+
+#[derive(Clone, Debug)]
+pub enum Step {
+    Install(TargetPackage),
+    Reinstall(TargetPackage),
+    Upgrade {
+        from: Box<Application>,
+        to: TargetPackage,
+    },
+    Remove(Application),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Plan {
+    pub steps: Vec<Step>,
+}
+
+impl Plan {
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Packages to download and stage, dependencies first.
+    pub fn targets(&self) -> Vec<TargetPackage> {
+        self.steps
+            .iter()
+            .filter_map(|s| match s {
+                Step::Install(t) | Step::Reinstall(t) => Some(t.clone()),
+                Step::Upgrade { to, .. } => Some(to.clone()),
+                Step::Remove(_) => None,
+            })
+            .collect()
+    }
+
+    pub fn removals(&self) -> Vec<Application> {
+        self.steps
+            .iter()
+            .filter_map(|s| match s {
+                Step::Remove(app) => Some(app.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// New packages that were asked for are explicit, other new ones are
+    /// dependencies. Installed packages keep their reason and renamed ones
+    /// take it over from the package they replace.
+    pub fn reason(&self, name: &str, requested: &[String]) -> InstallReason {
+        let target = match self.steps.iter().find_map(|s| match s {
+            Step::Install(t) if t.package.metadata.name == name => Some(t),
+            _ => None,
+        }) {
+            Some(t) => t,
+            None => return Application::install_reason(name),
+        };
+        let replaced = dependency_names(&target.package.replaces());
+        let mut inherited = self
+            .removals()
+            .into_iter()
+            .filter(|a| replaced.contains(&a.metadata.name))
+            .map(|a| Application::install_reason(&a.metadata.name))
+            .peekable();
+        if inherited.peek().is_some() {
+            return if inherited.any(|r| r == InstallReason::Explicit) {
+                InstallReason::Explicit
+            } else {
+                InstallReason::Dependency
+            };
+        }
+        if requested.iter().any(|r| target.package.provides_name(r)) {
+            InstallReason::Explicit
+        } else {
+            InstallReason::Dependency
+        }
+    }
+
+    pub fn print(&self) {
+        for step in self.steps.iter() {
+            match step {
+                Step::Install(t) => println!(
+                    "{} {} {} ({})",
+                    "install".green(),
+                    t.package.metadata.name,
+                    t.package.metadata.full_version(),
+                    t.repo
+                ),
+                Step::Reinstall(t) => println!(
+                    "{} {} {} ({})",
+                    "reinstall".blue(),
+                    t.package.metadata.name,
+                    t.package.metadata.full_version(),
+                    t.repo
+                ),
+                Step::Upgrade { from, to } => println!(
+                    "{} {} {} -> {} ({})",
+                    if PkgVersion::of(&to.package.metadata) < PkgVersion::of(&from.metadata) {
+                        "downgrade".magenta()
+                    } else {
+                        "upgrade".yellow()
+                    },
+                    to.package.metadata.name,
+                    from.metadata.full_version(),
+                    to.package.metadata.full_version(),
+                    to.repo
+                ),
+                Step::Remove(app) => println!(
+                    "{} {} {}",
+                    "remove".red(),
+                    app.metadata.name,
+                    app.metadata.full_version()
+                ),
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Requirement {
+    constraint: Constraint,
+    required_by: Option<String>,
+}
+
+impl Requirement {
+    // A requested `name=version` asks for exactly that version, release
+    // included when given, and may be met by older versions kept in the
+    // repo archives or the cache.
+    fn pin(&self) -> Option<PkgVersion> {
+        if self.required_by.is_none() && self.constraint.op == "=" {
+            Some(PkgVersion::parse(&self.constraint.version))
+        } else {
+            None
+        }
+    }
+
+    fn accepts(&self, app: &Application) -> bool {
+        let name = &self.constraint.name;
+        match self.pin() {
+            Some(pin) if &app.metadata.name == name => {
+                let mut have = PkgVersion::of(&app.metadata);
+                // no epoch given matches any
+                if !self.constraint.version.contains(':') {
+                    have.epoch = pin.epoch;
+                }
+                have == pin
+            }
+            _ => match app.provided_version(name) {
+                Some(v) => self.constraint.satisfied_by(&v),
+                None => false,
+            },
+        }
+    }
+
+    fn origin(&self) -> String {
+        match &self.required_by {
+            Some(by) => format!("{} (required by {})", self.constraint, by),
+            None => format!("{} (requested)", self.constraint),
+        }
+    }
+}
+
+// Picks one package for every requirement, trying candidates in repo
+// priority order and backtracking when a choice leads to a dead end.
+pub struct Resolver<'a> {
+    db: &'a BinDatabase,
+    config: &'a Configuration,
+    installed: Vec<Application>,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(db: &'a BinDatabase, config: &'a Configuration) -> Self {
+        Self {
+            db,
+            config,
+            installed: db.list_installed().unwrap_or_default(),
+        }
+    }
+
+    /// Work out what has to be installed, upgraded and removed so that every
+    /// requested package ends up installed with its run dependencies.
+    pub fn resolve(&self, requests: &[String]) -> Result<Plan, Vec<String>> {
+        let mut pending: Vec<Requirement> = Vec::new();
+        for request in requests.iter() {
+            match Constraint::parse(request) {
+                Ok(constraint) => pending.push(Requirement {
+                    constraint,
+                    required_by: None,
+                }),
+                Err(e) => return Err(vec![e]),
+            }
+        }
+
+        let mut chosen: Vec<TargetPackage> = Vec::new();
+        let mut failures: Vec<String> = Vec::new();
+        if !self.solve(&mut chosen, &pending, &mut failures) {
+            let mut errors: Vec<String> = vec![String::from("UNABLE TO RESOLVE")];
+            for failure in failures.into_iter() {
+                if !errors.contains(&failure) {
+                    errors.push(failure);
+                }
+            }
+            return Err(errors);
+        }
+
+        Ok(self.plan(chosen))
+    }
+
+    /// Check packages given as files, `(file, package)`, against each other
+    /// and what stays installed. Nothing is fetched for them, their run
+    /// dependencies have to be among the files or installed already.
+    pub fn resolve_files(&self, files: &[(String, Application)]) -> Result<Plan, Vec<String>> {
+        let chosen: Vec<TargetPackage> = files
+            .iter()
+            .map(|(file, app)| TargetPackage {
+                repo: file.clone(),
+                package_address: file.clone(),
+                package: app.clone(),
+            })
+            .collect();
+
+        let mut errors: Vec<String> = vec![String::from("UNABLE TO RESOLVE")];
+        let mut push = |e: String| {
+            if !errors.contains(&e) {
+                errors.push(e);
+            }
+        };
+        for (i, t) in chosen.iter().enumerate() {
+            let mut others = chosen.clone();
+            others.remove(i);
+            if let Err(reason) = self.compatible(t, &others) {
+                push(format!("{}: {}", t.repo, reason));
+            }
+            for dep in t.package.run_dependencies().iter() {
+                let req = match Constraint::parse(dep) {
+                    Ok(constraint) => Requirement {
+                        constraint,
+                        required_by: Some(t.package.metadata.name.clone()),
+                    },
+                    Err(e) => {
+                        push(format!("{}: {}", t.package.metadata.name, e));
+                        continue;
+                    }
+                };
+                if !self.satisfied(&req, &chosen) {
+                    push(format!(
+                        "{}: not installed, install it first or add its file",
+                        req.origin()
+                    ));
+                }
+            }
+        }
+        if errors.len() > 1 {
+            return Err(errors);
+        }
+        Ok(self.plan(chosen))
+    }
+
+    /// Work out which installed packages go away when `names` are removed.
+    /// Packages depending on them make this fail unless `cascade` is set, in
+    /// which case they are removed as well. `recursive` also takes along
+    /// dependencies that nothing else needs and that were not installed
+    /// explicitly.
+    pub fn removal(
+        &self,
+        names: &[String],
+        cascade: bool,
+        recursive: bool,
+    ) -> Result<Plan, Vec<String>> {
+        let mut errors: Vec<String> = Vec::new();
+        let mut removing: HashSet<String> = HashSet::new();
+        for name in names.iter() {
+            match self.installed.iter().find(|a| &a.metadata.name == name) {
+                Some(_) => {
+                    removing.insert(name.clone());
+                }
+                None => errors.push(format!("{} is not installed", name)),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        loop {
+            let broken: Vec<(&Application, Vec<String>)> = self
+                .installed
+                .iter()
+                .filter(|a| !removing.contains(&a.metadata.name))
+                .map(|a| (a, self.broken_by(a, &removing)))
+                .filter(|(_, deps)| !deps.is_empty())
+                .collect();
+            if broken.is_empty() {
+                break;
+            }
+            if !cascade {
+                let mut errors: Vec<String> = vec![String::from("UNABLE TO REMOVE")];
+                for (app, deps) in broken.iter() {
+                    errors.push(format!(
+                        "{} requires {}",
+                        app.metadata.name,
+                        deps.join(", ")
+                    ));
+                }
+                errors.push(String::from(
+                    "Nothing was removed, use --cascade to remove them as well",
+                ));
+                return Err(errors);
+            }
+            for (app, _) in broken.into_iter() {
+                removing.insert(app.metadata.name.clone());
+            }
+        }
+
+        if recursive {
+            loop {
+                let unneeded: Vec<String> = self
+                    .installed
+                    .iter()
+                    .filter(|a| !removing.contains(&a.metadata.name))
+                    .filter(|a| {
+                        Application::install_reason(&a.metadata.name) == InstallReason::Dependency
+                    })
+                    .filter(|a| self.needed_by(a, &removing))
+                    .filter(|a| {
+                        let mut with: HashSet<String> = removing.clone();
+                        with.insert(a.metadata.name.clone());
+                        self.installed
+                            .iter()
+                            .filter(|o| !with.contains(&o.metadata.name))
+                            .all(|o| self.broken_by(o, &with).is_empty())
+                    })
+                    .map(|a| a.metadata.name.clone())
+                    .collect();
+                if unneeded.is_empty() {
+                    break;
+                }
+                removing.extend(unneeded);
+            }
+        }
+
+        Ok(Plan {
+            steps: self
+                .installed
+                .iter()
+                .filter(|a| removing.contains(&a.metadata.name))
+                .map(|a| Step::Remove(a.clone()))
+                .collect(),
+        })
+    }
+
+    // Run dependencies of `app` that only packages in `removing` satisfy.
+    fn broken_by(&self, app: &Application, removing: &HashSet<String>) -> Vec<String> {
+        let satisfies = |c: &Constraint, a: &Application| match a.provided_version(&c.name) {
+            Some(v) => c.satisfied_by(&v),
+            None => false,
+        };
+        app.run_dependencies()
+            .iter()
+            .filter_map(|dep| Constraint::parse(dep).ok())
+            .filter(|c| {
+                let (gone, kept): (Vec<&Application>, Vec<&Application>) = self
+                    .installed
+                    .iter()
+                    .filter(|a| satisfies(c, a))
+                    .partition(|a| removing.contains(&a.metadata.name));
+                !gone.is_empty() && kept.is_empty()
+            })
+            .map(|c| c.to_string())
+            .collect()
+    }
+
+    // Whether a package in `removing` has `app` as a run dependency.
+    fn needed_by(&self, app: &Application, removing: &HashSet<String>) -> bool {
+        self.installed
+            .iter()
+            .filter(|a| removing.contains(&a.metadata.name))
+            .any(|a| {
+                dependency_names(&a.run_dependencies())
+                    .iter()
+                    .any(|dep| app.provides_name(dep))
+            })
+    }
+
+    // Every version of `name` for a pinned request: those in each repo in
+    // the order of the configuration, then those only found in the cache.
+    fn pinned(&self, name: &str) -> Vec<TargetPackage> {
+        let mut res: Vec<TargetPackage> = Vec::new();
+        for repo_meta in self.config.repos.iter() {
+            if let Some(repo) = self.db.repos.get(&repo_meta.name) {
+                for app in repo.versions(name).iter().rev() {
+                    if let Some(t) = self.db.target(self.config, &repo_meta.name, app) {
+                        res.push(t);
+                    }
+                }
+            }
+        }
+        for app in cache::versions(name).into_iter().rev() {
+            if !res
+                .iter()
+                .any(|t| t.package.archive_name() == app.archive_name())
+            {
+                res.push(TargetPackage {
+                    repo: CACHE_REPO.to_string(),
+                    package_address: String::new(),
+                    package: app,
+                });
+            }
+        }
+        res
+    }
+
+    // Every package that could satisfy `req`: an installed provider first,
+    // then each repo in the order of the configuration.
+    fn candidates(&self, req: &Requirement) -> Vec<TargetPackage> {
+        let name = req.constraint.name.as_str();
+        if req.pin().is_some() {
+            let pinned = self.pinned(name);
+            if !pinned.is_empty() {
+                return pinned;
+            }
+        }
+        let mut res: Vec<TargetPackage> = Vec::new();
+        for repo_meta in self.config.repos.iter() {
+            if let Some(repo) = self.db.repos.get(&repo_meta.name) {
+                let mut found: Vec<Application> = repo
+                    .providers(name)
+                    .into_iter()
+                    .filter(|app| app.provides_name(name))
+                    .collect();
+                found.sort_by(|a, b| {
+                    (a.metadata.name != name, &a.metadata.name)
+                        .cmp(&(b.metadata.name != name, &b.metadata.name))
+                });
+                for app in found.iter() {
+                    if let Some(t) = self.db.target(self.config, &repo_meta.name, app) {
+                        res.push(t);
+                    }
+                }
+            }
+        }
+        if let Some(pos) = res.iter().position(|t| {
+            self.installed
+                .iter()
+                .any(|a| a.metadata.name == t.package.metadata.name)
+        }) {
+            let t = res.remove(pos);
+            res.insert(0, t);
+        }
+        res
+    }
+
+    fn is_replaced(&self, app: &Application, chosen: &[TargetPackage]) -> bool {
+        chosen.iter().any(|t| {
+            t.package.metadata.name != app.metadata.name
+                && dependency_names(&t.package.replaces()).contains(&app.metadata.name)
+        })
+    }
+
+    // Installed packages that stay as they are next to `chosen`.
+    fn kept<'b>(&'b self, chosen: &'b [TargetPackage]) -> impl Iterator<Item = &'b Application> {
+        self.installed.iter().filter(move |a| {
+            !chosen
+                .iter()
+                .any(|t| t.package.metadata.name == a.metadata.name)
+                && !self.is_replaced(a, chosen)
+        })
+    }
+
+    fn compatible(&self, cand: &TargetPackage, chosen: &[TargetPackage]) -> Result<(), String> {
+        let app = &cand.package;
+        let mut with: Vec<TargetPackage> = chosen.to_vec();
+        with.push(cand.clone());
+
+        for other in chosen.iter() {
+            if app.conflicts_with(&other.package) {
+                return Err(format!(
+                    "{} conflicts with {}",
+                    app.metadata.name, other.package.metadata.name
+                ));
+            }
+            for dep in other.package.run_dependencies().iter() {
+                if let Ok(c) = Constraint::parse(dep) {
+                    if c.name == app.metadata.name && !c.satisfied_by(&app.metadata.full_version())
+                    {
+                        return Err(format!(
+                            "{} {} does not satisfy {} (required by {})",
+                            app.metadata.name,
+                            app.metadata.full_version(),
+                            c,
+                            other.package.metadata.name
+                        ));
+                    }
+                }
+            }
+        }
+
+        for other in self.kept(&with) {
+            if app.conflicts_with(other) {
+                return Err(format!(
+                    "{} conflicts with installed {}, remove it first",
+                    app.metadata.name, other.metadata.name
+                ));
+            }
+            for dep in other.run_dependencies().iter() {
+                if let Ok(c) = Constraint::parse(dep) {
+                    if c.name == app.metadata.name && !c.satisfied_by(&app.metadata.full_version())
+                    {
+                        return Err(format!(
+                            "{} {} breaks installed {}, which requires {}",
+                            app.metadata.name,
+                            app.metadata.full_version(),
+                            other.metadata.name,
+                            c
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Held packages only change when they are asked for by name.
+    fn unheld(&self, cand: &TargetPackage, req: &Requirement) -> Result<(), String> {
+        let name = &cand.package.metadata.name;
+        if req.required_by.is_none() || !self.config.is_held(name) {
+            return Ok(());
+        }
+        match self.installed.iter().find(|a| &a.metadata.name == name) {
+            Some(a) if a.archive_name() != cand.package.archive_name() => {
+                Err(format!("{} is held at {}", name, a.metadata.full_version()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn solve(
+        &self,
+        chosen: &mut Vec<TargetPackage>,
+        pending: &[Requirement],
+        failures: &mut Vec<String>,
+    ) -> bool {
+        // skip everything that is already taken care of
+        let mut start = 0;
+        while start < pending.len() && self.satisfied(&pending[start], chosen) {
+            start += 1;
+        }
+        let req = match pending.get(start) {
+            Some(r) => r,
+            None => return true,
+        };
+        let rest = &pending[start + 1..];
+        let name = &req.constraint.name;
+
+        let candidates = self.candidates(req);
+        if candidates.is_empty() {
+            failures.push(format!("{}: no package provides {}", req.origin(), name));
+            return false;
+        }
+
+        for cand in candidates.into_iter() {
+            if !req.accepts(&cand.package) {
+                let version = match req.pin() {
+                    Some(_) => cand.package.metadata.full_version(),
+                    None => cand.package.provided_version(name).unwrap_or_default(),
+                };
+                failures.push(format!(
+                    "{}: {} has {} {}",
+                    req.origin(),
+                    cand.repo,
+                    cand.package.metadata.name,
+                    version
+                ));
+                continue;
+            }
+            if let Err(reason) = self.unheld(&cand, req) {
+                failures.push(format!("{}: {}", req.origin(), reason));
+                continue;
+            }
+            if chosen
+                .iter()
+                .any(|t| t.package.metadata.name == cand.package.metadata.name)
+            {
+                continue;
+            }
+            if let Err(reason) = self.compatible(&cand, chosen) {
+                failures.push(format!("{}: {}", req.origin(), reason));
+                continue;
+            }
+
+            let mut next: Vec<Requirement> = rest.to_vec();
+            let mut valid = true;
+            for dep in cand.package.run_dependencies().iter() {
+                match Constraint::parse(dep) {
+                    Ok(constraint) => next.push(Requirement {
+                        constraint,
+                        required_by: Some(cand.package.metadata.name.clone()),
+                    }),
+                    Err(e) => {
+                        failures.push(format!("{}: {}", cand.package.metadata.name, e));
+                        valid = false;
+                    }
+                }
+            }
+            if !valid {
+                continue;
+            }
+
+            chosen.push(cand);
+            if self.solve(chosen, &next, failures) {
+                return true;
+            }
+            chosen.pop();
+        }
+        false
+    }
+
+    // A dependency is fine if something chosen or kept installed provides a
+    // matching version. Requested packages are always taken from a repo.
+    fn satisfied(&self, req: &Requirement, chosen: &[TargetPackage]) -> bool {
+        let by_chosen = chosen.iter().any(|t| req.accepts(&t.package));
+        if by_chosen || req.required_by.is_none() {
+            return by_chosen;
+        }
+        self.kept(chosen).any(|a| req.accepts(a))
+    }
+
+    fn plan(&self, chosen: Vec<TargetPackage>) -> Plan {
+        let mut steps: Vec<Step> = self
+            .installed
+            .iter()
+            .filter(|a| self.is_replaced(a, &chosen))
+            .map(|a| Step::Remove(a.clone()))
+            .collect();
+
+        // dependencies before the packages using them, cycles are cut where
+        // they are first met
+        let mut ordered: Vec<TargetPackage> = Vec::new();
+        let mut visited: HashSet<String> = HashSet::new();
+        for t in chosen.iter() {
+            self.visit(t, &chosen, &mut visited, &mut ordered);
+        }
+
+        for t in ordered.into_iter() {
+            let installed = self
+                .installed
+                .iter()
+                .find(|a| a.metadata.name == t.package.metadata.name);
+            steps.push(match installed {
+                None => Step::Install(t),
+                Some(a) if PkgVersion::of(&a.metadata) == PkgVersion::of(&t.package.metadata) => {
+                    Step::Reinstall(t)
+                }
+                Some(a) => Step::Upgrade {
+                    from: Box::new(a.clone()),
+                    to: t,
+                },
+            });
+        }
+        Plan { steps }
+    }
+
+    fn visit(
+        &self,
+        t: &TargetPackage,
+        chosen: &[TargetPackage],
+        visited: &mut HashSet<String>,
+        ordered: &mut Vec<TargetPackage>,
+    ) {
+        if !visited.insert(t.package.metadata.name.clone()) {
+            return;
+        }
+        for dep in dependency_names(&t.package.run_dependencies()).iter() {
+            if let Some(d) = chosen.iter().find(|c| c.package.provides_name(dep)) {
+                self.visit(d, chosen, visited, ordered);
+            }
+        }
+        ordered.push(t.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{app, index, with};
+    use crate::{RepoMeta, SigLevel};
+
+    // Repos in priority order, each an index of its own.
+    fn resolve(
+        repos: &[(&str, Vec<Application>)],
+        installed: Vec<Application>,
+        requests: &[&str],
+    ) -> Result<Vec<String>, Vec<String>> {
+        let mut db = BinDatabase::new();
+        let mut config = Configuration {
+            repos: Vec::new(),
+            ..Configuration::default()
+        };
+        for (name, apps) in repos.iter() {
+            db.repos.insert(name.to_string(), index(apps));
+            config.repos.push(RepoMeta {
+                name: name.to_string(),
+                static_address: format!("http://localhost/{}/", name),
+                update_address: String::new(),
+                signatures: SigLevel::Never,
+            });
+        }
+        let resolver = Resolver {
+            db: &db,
+            config: &config,
+            installed,
+        };
+        let requests: Vec<String> = requests.iter().map(|r| r.to_string()).collect();
+        resolver.resolve(&requests).map(|plan| {
+            plan.steps
+                .iter()
+                .map(|s| match s {
+                    Step::Install(t) => format!(
+                        "install {} {} ({})",
+                        t.package.metadata.name,
+                        t.package.metadata.full_version(),
+                        t.repo
+                    ),
+                    Step::Reinstall(t) => format!(
+                        "reinstall {} {}",
+                        t.package.metadata.name,
+                        t.package.metadata.full_version()
+                    ),
+                    Step::Upgrade { from, to } => format!(
+                        "upgrade {} {} -> {}",
+                        to.package.metadata.name,
+                        from.metadata.full_version(),
+                        to.package.metadata.full_version()
+                    ),
+                    Step::Remove(a) => format!("remove {}", a.metadata.name),
+                })
+                .collect()
+        })
+    }
+
+    #[test]
+    fn dependencies_come_first() {
+        let core = vec![app("a", "1.0", &["b>=1.0"]), app("b", "1.2", &[])];
+        assert_eq!(
+            resolve(&[("core", core)], vec![], &["a"]).unwrap(),
+            vec!["install b 1.2-1 (core)", "install a 1.0-1 (core)"]
+        );
+    }
+
+    #[test]
+    fn backtracks_to_the_next_provider() {
+        let core = vec![
+            app("a", "1.0", &["sh"]),
+            with(app("bash", "5.1", &["readline>=8"]), "provides", &["sh"]),
+            with(app("dash", "0.5", &[]), "provides", &["sh"]),
+            app("readline", "7.0", &[]),
+        ];
+        assert_eq!(
+            resolve(&[("core", core)], vec![], &["a"]).unwrap(),
+            vec!["install dash 0.5-1 (core)", "install a 1.0-1 (core)"]
+        );
+    }
+
+    #[test]
+    fn picks_providers_by_name_repo_and_what_is_installed() {
+        let core = vec![
+            app("a", "1.0", &["sh"]),
+            with(app("dash", "0.5", &[]), "provides", &["sh"]),
+            with(app("bash", "5.1", &[]), "provides", &["sh"]),
+        ];
+        let extra = vec![app("sh", "1.0", &[])];
+        // an earlier repo wins, then a package of that name, then by name
+        assert_eq!(
+            resolve(&[("core", core.clone())], vec![], &["a"]).unwrap(),
+            vec!["install bash 5.1-1 (core)", "install a 1.0-1 (core)"]
+        );
+        assert_eq!(
+            resolve(&[("extra", extra), ("core", core.clone())], vec![], &["a"]).unwrap(),
+            vec!["install sh 1.0-1 (extra)", "install a 1.0-1 (core)"]
+        );
+        // an installed provider is kept
+        let dash = with(app("dash", "0.5", &[]), "provides", &["sh"]);
+        assert_eq!(
+            resolve(&[("core", core)], vec![dash], &["a"]).unwrap(),
+            vec!["install a 1.0-1 (core)"]
+        );
+    }
+
+    #[test]
+    fn reports_conflicts() {
+        let core = vec![
+            with(app("a", "1.0", &[]), "conflicts", &["b"]),
+            app("b", "1.0", &[]),
+        ];
+        assert_eq!(
+            resolve(&[("core", core.clone())], vec![], &["a", "b"]).unwrap_err(),
+            vec!["UNABLE TO RESOLVE", "b (requested): b conflicts with a"]
+        );
+        assert_eq!(
+            resolve(&[("core", core)], vec![app("b", "1.0", &[])], &["a"]).unwrap_err(),
+            vec![
+                "UNABLE TO RESOLVE",
+                "a (requested): a conflicts with installed b, remove it first"
+            ]
+        );
+    }
+
+    #[test]
+    fn replaces_renamed_packages() {
+        let core = vec![with(
+            with(app("newname", "1.0", &[]), "replaces", &["oldname"]),
+            "conflicts",
+            &["oldname"],
+        )];
+        assert_eq!(
+            resolve(
+                &[("core", core)],
+                vec![app("oldname", "1.0", &[])],
+                &["newname"]
+            )
+            .unwrap(),
+            vec!["remove oldname", "install newname 1.0-1 (core)"]
+        );
+    }
+
+    #[test]
+    fn explains_what_cannot_be_satisfied() {
+        let core = vec![app("a", "1.0", &["libx>=2"]), app("libx", "1.4", &[])];
+        let extra = vec![app("libx", "1.9", &[])];
+        assert_eq!(
+            resolve(&[("core", core), ("extra", extra)], vec![], &["a"]).unwrap_err(),
+            vec![
+                "UNABLE TO RESOLVE",
+                "libx>=2 (required by a): core has libx 1.4-1",
+                "libx>=2 (required by a): extra has libx 1.9-1",
+            ]
+        );
+        assert_eq!(
+            resolve(&[("core", vec![])], vec![], &["nothere"]).unwrap_err(),
+            vec![
+                "UNABLE TO RESOLVE",
+                "nothere (requested): no package provides nothere"
+            ]
+        );
+    }
+
+    #[test]
+    fn compares_package_versions_of_installed_packages() {
+        let core = vec![app("a", "1:1.0-1", &[]), app("b", "2.0-1", &[])];
+        let installed = vec![app("a", "1.0-1", &[]), app("b", "2.0-1", &[])];
+        assert_eq!(
+            resolve(&[("core", core)], installed, &["a", "b"]).unwrap(),
+            vec!["upgrade a 1.0-1 -> 1:1.0-1", "reinstall b 2.0-1"]
+        );
+    }
+
+    #[test]
+    fn checks_files_against_installed_packages() {
+        let db = BinDatabase::new();
+        let config = Configuration::default();
+        let resolver = Resolver {
+            db: &db,
+            config: &config,
+            installed: vec![app("libx", "1.4", &[]), app("b", "1.0", &[])],
+        };
+        let file = |a: Application| (format!("{}.app", a.archive_name()), a);
+        let a = file(app("a", "1.0", &["libx>=2"]));
+        let errors = resolver
+            .resolve_files(std::slice::from_ref(&a))
+            .unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                "UNABLE TO RESOLVE",
+                "libx>=2 (required by a): not installed, install it first or add its file"
+            ]
+        );
+
+        let libx = file(app("libx", "2.0", &[]));
+        let plan = resolver.resolve_files(&[a.clone(), libx]).unwrap();
+        assert_eq!(
+            plan.targets()
+                .iter()
+                .map(|t| t.package_address.as_str())
+                .collect::<Vec<_>>(),
+            vec!["libx-2.0-1-x86_64.app", "a-1.0-1-x86_64.app"]
+        );
+
+        let c = file(with(app("c", "1.0", &[]), "conflicts", &["b"]));
+        assert_eq!(
+            resolver.resolve_files(&[c]).unwrap_err(),
+            vec![
+                "UNABLE TO RESOLVE",
+                "c-1.0-1-x86_64.app: c conflicts with installed b, remove it first"
+            ]
+        );
+    }
+}
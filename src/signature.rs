@@ -0,0 +1,575 @@
+use crate::{utils::download_http, SigLevel, KEYS_DIR};
+use colored::Colorize;
+use ed25519_dalek::{Keypair, Signer, Verifier};
+use rand::rngs::OsRng;
+use std::convert::TryFrom;
+use std::{
+    fs::{self, File},
+    io::Read,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+// Keys and signatures use the minisign text format, so keys made with
+// `minisign -G` and signatures made with `minisign -S` are accepted as well.
+//
+//   untrusted comment: <anything>
+//   base64(algorithm || key id || signature)
+//   trusted comment: <signed text>
+//   base64(signature over signature || trusted comment)
+//
+// "Ed" signs the file itself, "ED" signs its BLAKE2b-512 hash.
+const ALG_PURE: &[u8; 2] = b"Ed";
+const ALG_HASHED: &[u8; 2] = b"ED";
+
+#[derive(Clone, Debug)]
+pub struct PublicKey {
+    pub id: [u8; 8],
+    key: ed25519_dalek::PublicKey,
+}
+
+pub struct SecretKey {
+    pub id: [u8; 8],
+    keypair: Keypair,
+}
+
+struct Signature {
+    algorithm: [u8; 2],
+    key_id: [u8; 8],
+    signature: ed25519_dalek::Signature,
+    trusted_comment: String,
+    global_signature: ed25519_dalek::Signature,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Keyring {
+    pub keys: Vec<PublicKey>,
+}
+
+impl PublicKey {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let bytes = decode_line(text, 1)?;
+        if bytes.len() != 42 || &bytes[..2] != ALG_PURE {
+            return Err(String::from("not an ed25519 public key"));
+        }
+        let mut id = [0u8; 8];
+        id.copy_from_slice(&bytes[2..10]);
+        match ed25519_dalek::PublicKey::from_bytes(&bytes[10..]) {
+            Ok(key) => Ok(Self { id, key }),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text).map_err(|e| format!("{}: {}", path.display(), e)),
+            Err(e) => Err(format!("{}: {}", path.display(), e)),
+        }
+    }
+
+    pub fn id_hex(&self) -> String {
+        key_id_hex(&self.id)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut bytes: Vec<u8> = ALG_PURE.to_vec();
+        bytes.extend_from_slice(&self.id);
+        bytes.extend_from_slice(self.key.as_bytes());
+        format!(
+            "untrusted comment: minisign public key {}\n{}\n",
+            self.id_hex(),
+            base64::encode(&bytes)
+        )
+    }
+}
+
+impl SecretKey {
+    pub fn generate() -> Self {
+        let mut rng = OsRng {};
+        let keypair = Keypair::generate(&mut rng);
+        let mut id = [0u8; 8];
+        id.copy_from_slice(&keypair.public.as_bytes()[..8]);
+        Self { id, keypair }
+    }
+
+    // Not the (encrypted) minisign secret key format, only bin-repo reads it.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let bytes = decode_line(text, 1)?;
+        if bytes.len() != 74 || &bytes[..2] != ALG_PURE {
+            return Err(String::from("not an ed25519 secret key"));
+        }
+        let mut id = [0u8; 8];
+        id.copy_from_slice(&bytes[2..10]);
+        match Keypair::from_bytes(&bytes[10..]) {
+            Ok(keypair) => Ok(Self { id, keypair }),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text).map_err(|e| format!("{}: {}", path.display(), e)),
+            Err(e) => Err(format!("{}: {}", path.display(), e)),
+        }
+    }
+
+    pub fn public(&self) -> PublicKey {
+        PublicKey {
+            id: self.id,
+            key: self.keypair.public,
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut bytes: Vec<u8> = ALG_PURE.to_vec();
+        bytes.extend_from_slice(&self.id);
+        bytes.extend_from_slice(&self.keypair.to_bytes());
+        format!(
+            "untrusted comment: pi secret key {}\n{}\n",
+            key_id_hex(&self.id),
+            base64::encode(&bytes)
+        )
+    }
+
+    /// Write a detached signature next to `file` as `<file>.sig`.
+    pub fn sign_file(&self, file: &Path) -> std::io::Result<PathBuf> {
+        let digest = hash_file(file)?;
+        let signature = self.keypair.sign(&digest);
+
+        let file_name = file.file_name().unwrap_or_default().to_string_lossy();
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let trusted_comment = format!("timestamp:{}\tfile:{}\thashed", timestamp, file_name);
+        let mut global: Vec<u8> = signature.to_bytes().to_vec();
+        global.extend_from_slice(trusted_comment.as_bytes());
+        let global_signature = self.keypair.sign(&global);
+
+        let mut bytes: Vec<u8> = ALG_HASHED.to_vec();
+        bytes.extend_from_slice(&self.id);
+        bytes.extend_from_slice(&signature.to_bytes());
+
+        let sig_path = sig_path(file);
+        fs::write(
+            &sig_path,
+            format!(
+                "untrusted comment: signature from pi secret key {}\n{}\ntrusted comment: {}\n{}\n",
+                key_id_hex(&self.id),
+                base64::encode(&bytes),
+                trusted_comment,
+                base64::encode(&global_signature.to_bytes()[..])
+            ),
+        )?;
+        Ok(sig_path)
+    }
+}
+
+impl Signature {
+    fn parse(text: &str) -> Result<Self, String> {
+        let bytes = decode_line(text, 1)?;
+        if bytes.len() != 74 || (&bytes[..2] != ALG_PURE && &bytes[..2] != ALG_HASHED) {
+            return Err(String::from("unsupported signature"));
+        }
+        let mut algorithm = [0u8; 2];
+        algorithm.copy_from_slice(&bytes[..2]);
+        let mut key_id = [0u8; 8];
+        key_id.copy_from_slice(&bytes[2..10]);
+        let signature = match ed25519_dalek::Signature::try_from(&bytes[10..]) {
+            Ok(s) => s,
+            Err(e) => return Err(e.to_string()),
+        };
+
+        let trusted_comment = match text.lines().nth(2) {
+            Some(line) if line.starts_with("trusted comment: ") => {
+                line.trim_start_matches("trusted comment: ").to_string()
+            }
+            _ => return Err(String::from("missing trusted comment")),
+        };
+        let global = decode_line(text, 3)?;
+        let global_signature = match ed25519_dalek::Signature::try_from(&global[..]) {
+            Ok(s) => s,
+            Err(e) => return Err(e.to_string()),
+        };
+
+        Ok(Self {
+            algorithm,
+            key_id,
+            signature,
+            trusted_comment,
+            global_signature,
+        })
+    }
+}
+
+impl Keyring {
+    /// Every `*.pub` file in `CONF_DIR/keys`.
+    pub fn load() -> Self {
+        let mut keys: Vec<PublicKey> = Vec::new();
+        if let Ok(entries) = fs::read_dir(KEYS_DIR.as_path()) {
+            let mut paths: Vec<PathBuf> =
+                entries.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+            paths.sort();
+            for path in paths.iter() {
+                if path.extension().is_some_and(|e| e == "pub") {
+                    match PublicKey::from_file(path) {
+                        Ok(key) => keys.push(key),
+                        Err(e) => eprintln!("{}", e.yellow()),
+                    }
+                }
+            }
+        }
+        Self { keys }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn get(&self, id: &[u8; 8]) -> Option<&PublicKey> {
+        self.keys.iter().find(|k| &k.id == id)
+    }
+
+    /// Copy a public key into the keyring as `<KEYID>.pub`.
+    pub fn add(key_file: &Path) -> Result<PublicKey, String> {
+        let key = PublicKey::from_file(key_file)?;
+        fs::create_dir_all(KEYS_DIR.as_path()).map_err(|e| e.to_string())?;
+        fs::write(
+            KEYS_DIR.join(format!("{}.pub", key.id_hex())),
+            key.to_text(),
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(key)
+    }
+
+    pub fn remove(id: &str) -> Result<(), String> {
+        let keyring = Self::load();
+        match keyring
+            .keys
+            .iter()
+            .find(|k| k.id_hex() == id.to_uppercase())
+        {
+            Some(_) => fs::remove_file(KEYS_DIR.join(format!("{}.pub", id.to_uppercase())))
+                .map_err(|e| format!("{}: {}", id, e)),
+            None => Err(format!("{}: no such key", id)),
+        }
+    }
+
+    /// Check `file` against its detached signature and return the trusted
+    /// comment on success.
+    pub fn verify(&self, file: &Path, sig_file: &Path) -> Result<String, String> {
+        let text = match fs::read_to_string(sig_file) {
+            Ok(t) => t,
+            Err(e) => return Err(format!("{}: {}", sig_file.display(), e)),
+        };
+        let sig = Signature::parse(&text).map_err(|e| format!("{}: {}", sig_file.display(), e))?;
+        let key = match self.get(&sig.key_id) {
+            Some(k) => k,
+            None => {
+                return Err(format!(
+                    "{}: signed with unknown key {}",
+                    file.display(),
+                    key_id_hex(&sig.key_id)
+                ))
+            }
+        };
+
+        let message = if &sig.algorithm == ALG_HASHED {
+            hash_file(file)
+        } else {
+            fs::read(file)
+        };
+        let message = message.map_err(|e| format!("{}: {}", file.display(), e))?;
+        if key.key.verify(&message, &sig.signature).is_err() {
+            return Err(format!("{}: invalid signature", file.display()));
+        }
+
+        let mut global: Vec<u8> = sig.signature.to_bytes().to_vec();
+        global.extend_from_slice(sig.trusted_comment.as_bytes());
+        if key.key.verify(&global, &sig.global_signature).is_err() {
+            return Err(format!("{}: invalid trusted comment", file.display()));
+        }
+        Ok(sig.trusted_comment)
+    }
+}
+
+pub fn sig_path(file: &Path) -> PathBuf {
+    let mut name = file.as_os_str().to_owned();
+    name.push(".sig");
+    PathBuf::from(name)
+}
+
+/// Fetch `<address>.sig` next to an already downloaded file and check it
+/// against the keyring, as far as `level` asks for.
+pub async fn verify_download(
+    keyring: &Keyring,
+    level: SigLevel,
+    file: &Path,
+    address: &str,
+) -> Result<(), String> {
+    if level == SigLevel::Never {
+        return Ok(());
+    }
+    let sig_file = sig_path(file);
+    if sig_file.exists() {
+        fs::remove_file(&sig_file).map_err(|e| e.to_string())?;
+    }
+    let sig_name = sig_file.file_name().unwrap().to_string_lossy().to_string();
+    if let Err(e) = download_http(
+        sig_file.to_str().unwrap(),
+        &sig_name,
+        &format!("{}.sig", address),
+    )
+    .await
+    {
+        return match level {
+            SigLevel::Optional => {
+                println!(
+                    "{} {}",
+                    "warning: no signature for".yellow(),
+                    file.display()
+                );
+                Ok(())
+            }
+            _ => Err(format!("{}: missing signature: {}", file.display(), e)),
+        };
+    }
+
+    keyring.verify(file, &sig_file).map(|_| ())
+}
+
+/// Check a file already on disk, e.g. a cached package, against the
+/// signature next to it, as far as `level` asks for.
+pub fn verify_local(keyring: &Keyring, level: SigLevel, file: &Path) -> Result<(), String> {
+    let sig_file = sig_path(file);
+    match level {
+        SigLevel::Never => Ok(()),
+        SigLevel::Optional if !sig_file.exists() => Ok(()),
+        _ => keyring.verify(file, &sig_file).map(|_| ()),
+    }
+}
+
+fn hash_file(file: &Path) -> std::io::Result<Vec<u8>> {
+    let mut f = File::open(file)?;
+    let mut state = blake2b_simd::State::new();
+    let mut buffer = [0u8; 65536];
+    loop {
+        let n = f.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        state.update(&buffer[..n]);
+    }
+    Ok(state.finalize().as_bytes().to_vec())
+}
+
+fn decode_line(text: &str, line: usize) -> Result<Vec<u8>, String> {
+    match text.lines().nth(line) {
+        Some(l) => base64::decode(l.trim()).map_err(|e| e.to_string()),
+        None => Err(String::from("truncated file")),
+    }
+}
+
+fn key_id_hex(id: &[u8; 8]) -> String {
+    let mut le = *id;
+    le.reverse();
+    le.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_path;
+
+    // A file with `data` and a keyring holding the public half of `key`.
+    fn setup(data: &[u8]) -> (SecretKey, Keyring, PathBuf) {
+        let key = SecretKey::generate();
+        let keyring = Keyring {
+            keys: vec![key.public()],
+        };
+        let file = temp_path("file.app");
+        fs::write(&file, data).unwrap();
+        (key, keyring, file)
+    }
+
+    // What `minisign -S -l` writes: an "Ed" signature over the file itself.
+    fn sign_pure(key: &SecretKey, file: &Path) {
+        let signature = key.keypair.sign(&fs::read(file).unwrap());
+        let trusted_comment = "timestamp:0\tfile:file.app";
+        let mut global: Vec<u8> = signature.to_bytes().to_vec();
+        global.extend_from_slice(trusted_comment.as_bytes());
+        let mut bytes: Vec<u8> = ALG_PURE.to_vec();
+        bytes.extend_from_slice(&key.id);
+        bytes.extend_from_slice(&signature.to_bytes());
+        fs::write(
+            sig_path(file),
+            format!(
+                "untrusted comment: minisign\n{}\ntrusted comment: {}\n{}\n",
+                base64::encode(&bytes),
+                trusted_comment,
+                base64::encode(&key.keypair.sign(&global).to_bytes()[..])
+            ),
+        )
+        .unwrap();
+    }
+
+    // Replace line `n` of the signature of `file`.
+    fn edit_sig(file: &Path, n: usize, line: &str) {
+        let text = fs::read_to_string(sig_path(file)).unwrap();
+        let mut lines: Vec<String> = text.lines().map(|l| l.to_string()).collect();
+        lines[n] = line.to_string();
+        fs::write(sig_path(file), lines.join("\n") + "\n").unwrap();
+    }
+
+    fn clean(file: &Path) {
+        fs::remove_file(file).ok();
+        fs::remove_file(sig_path(file)).ok();
+    }
+
+    #[test]
+    fn keys_round_trip() {
+        let key = SecretKey::generate();
+        let public = PublicKey::parse(&key.public().to_text()).unwrap();
+        assert_eq!(public.id, key.id);
+        assert_eq!(public.id_hex().len(), 16);
+        assert!(key.public().to_text().contains(&public.id_hex()));
+        let secret = SecretKey::parse(&key.to_text()).unwrap();
+        assert_eq!(secret.public().key, key.public().key);
+
+        assert!(PublicKey::parse("untrusted comment: x\n").is_err());
+        assert!(PublicKey::parse(&key.to_text()).is_err());
+        assert!(SecretKey::parse(&key.public().to_text()).is_err());
+    }
+
+    #[test]
+    fn verifies_signed_files() {
+        let (key, keyring, file) = setup(b"package");
+        key.sign_file(&file).unwrap();
+        let comment = keyring.verify(&file, &sig_path(&file)).unwrap();
+        assert!(comment.starts_with("timestamp:"));
+        assert!(comment.ends_with(&format!(
+            "\tfile:{}\thashed",
+            file.file_name().unwrap().to_string_lossy()
+        )));
+        clean(&file);
+    }
+
+    #[test]
+    fn rejects_tampered_files() {
+        let (key, keyring, file) = setup(b"package");
+        key.sign_file(&file).unwrap();
+        fs::write(&file, b"packagf").unwrap();
+        assert_eq!(
+            keyring.verify(&file, &sig_path(&file)).unwrap_err(),
+            format!("{}: invalid signature", file.display())
+        );
+        clean(&file);
+    }
+
+    #[test]
+    fn rejects_tampered_trusted_comments() {
+        let (key, keyring, file) = setup(b"package");
+        key.sign_file(&file).unwrap();
+        edit_sig(
+            &file,
+            2,
+            "trusted comment: timestamp:0\tfile:other.app\thashed",
+        );
+        assert_eq!(
+            keyring.verify(&file, &sig_path(&file)).unwrap_err(),
+            format!("{}: invalid trusted comment", file.display())
+        );
+        clean(&file);
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        let (_, keyring, file) = setup(b"package");
+        let other = SecretKey::generate();
+        other.sign_file(&file).unwrap();
+        assert_eq!(
+            keyring.verify(&file, &sig_path(&file)).unwrap_err(),
+            format!(
+                "{}: signed with unknown key {}",
+                file.display(),
+                other.public().id_hex()
+            )
+        );
+        assert!(Keyring::default().verify(&file, &sig_path(&file)).is_err());
+        clean(&file);
+    }
+
+    #[test]
+    fn tells_pure_and_hashed_signatures_apart() {
+        let (key, keyring, file) = setup(b"package");
+        sign_pure(&key, &file);
+        assert_eq!(
+            keyring.verify(&file, &sig_path(&file)).unwrap(),
+            "timestamp:0\tfile:file.app"
+        );
+
+        // the same signature taken for one over the hash
+        let text = fs::read_to_string(sig_path(&file)).unwrap();
+        let mut bytes = base64::decode(text.lines().nth(1).unwrap()).unwrap();
+        bytes[..2].copy_from_slice(ALG_HASHED);
+        edit_sig(&file, 1, &base64::encode(&bytes));
+        assert_eq!(
+            keyring.verify(&file, &sig_path(&file)).unwrap_err(),
+            format!("{}: invalid signature", file.display())
+        );
+
+        bytes[..2].copy_from_slice(b"XX");
+        edit_sig(&file, 1, &base64::encode(&bytes));
+        assert!(keyring
+            .verify(&file, &sig_path(&file))
+            .unwrap_err()
+            .ends_with("unsupported signature"));
+        clean(&file);
+    }
+
+    #[test]
+    fn checks_local_files_by_level() {
+        let (key, keyring, file) = setup(b"package");
+        // no signature
+        assert!(verify_local(&keyring, SigLevel::Never, &file).is_ok());
+        assert!(verify_local(&keyring, SigLevel::Optional, &file).is_ok());
+        assert!(verify_local(&keyring, SigLevel::Required, &file).is_err());
+
+        // a bad one
+        SecretKey::generate().sign_file(&file).unwrap();
+        assert!(verify_local(&keyring, SigLevel::Never, &file).is_ok());
+        assert!(verify_local(&keyring, SigLevel::Optional, &file).is_err());
+        assert!(verify_local(&keyring, SigLevel::Required, &file).is_err());
+
+        key.sign_file(&file).unwrap();
+        assert!(verify_local(&keyring, SigLevel::Optional, &file).is_ok());
+        assert!(verify_local(&keyring, SigLevel::Required, &file).is_ok());
+        clean(&file);
+    }
+
+    #[tokio::test]
+    async fn downloads_signatures_by_level() {
+        let (key, keyring, file) = setup(b"package");
+        // nothing listens there
+        let address = "http://127.0.0.1:9/file.app";
+        key.sign_file(&file).unwrap();
+        assert!(verify_download(&keyring, SigLevel::Never, &file, address)
+            .await
+            .is_ok());
+        // a signature left from before does not count
+        assert!(sig_path(&file).exists());
+        assert!(
+            verify_download(&keyring, SigLevel::Required, &file, address)
+                .await
+                .unwrap_err()
+                .starts_with(&format!("{}: missing signature", file.display()))
+        );
+        assert!(!sig_path(&file).exists());
+        assert!(
+            verify_download(&keyring, SigLevel::Optional, &file, address)
+                .await
+                .is_ok()
+        );
+        clean(&file);
+    }
+}
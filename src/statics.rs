@@ -11,17 +11,23 @@ lazy_static! {
     pub static ref ROOT_DIR: PathBuf = root();
     pub static ref LIB_DIR: PathBuf = ROOT_DIR.join("var/lib/store");
     pub static ref LOCAL_DIR: PathBuf = LIB_DIR.join("local");
+    pub static ref LOCAL_INDEX: PathBuf = LIB_DIR.join("local.idx");
+    pub static ref LOCK_FILE: PathBuf = LIB_DIR.join("db.lck");
     pub static ref SYNC_DIR: PathBuf = LIB_DIR.join("sync");
     pub static ref CACHE_DIR: PathBuf = LIB_DIR.join("cache");
+    pub static ref TX_DIR: PathBuf = LIB_DIR.join("transaction");
     pub static ref CONF_DIR: PathBuf = ROOT_DIR.join("etc/store");
 
     pub static ref CONF_FILE: PathBuf = CONF_DIR.join("store.conf");
-    pub static ref MANI_FILE: PathBuf = PKG_DIR.join("manifest.yml");
+    pub static ref KEYS_DIR: PathBuf = CONF_DIR.join("keys");
+    pub static ref HOOKS_DIR: PathBuf = CONF_DIR.join("hooks.d");
 
     // Package Builder
     pub static ref CWD_DIR: PathBuf = cwd();
     pub static ref SRC_DIR: PathBuf = cwd().join("source");
     pub static ref PKG_DIR: PathBuf = cwd().join("package");
+    // next to pkgbuild.yml, the copy in the package goes under .PI/
+    pub static ref MANI_FILE: PathBuf = cwd().join("manifest.yml");
 
     pub static ref PKG_FILE: PathBuf = cwd().join("pkgbuild.yml");
 
@@ -0,0 +1,159 @@
+use crate::{
+    signature::{sig_path, verify_download, Keyring},
+    transaction::suffixed,
+    BinRepo, Index, RepoMeta, SYNC_DIR,
+};
+use colored::Colorize;
+use reqwest::{header, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
+use url::Url;
+
+// What syncing did to one repo.
+#[derive(Clone, Debug)]
+pub enum SyncStatus {
+    UpToDate,
+    Updated(Index),
+    Failed(String),
+}
+
+impl fmt::Display for SyncStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncStatus::UpToDate => write!(f, "{}", "up to date".green()),
+            SyncStatus::Updated(_) => write!(f, "{}", "updated".blue()),
+            SyncStatus::Failed(e) => write!(f, "{}", format!("failed: {}", e).red()),
+        }
+    }
+}
+
+// Cache validators the server sent with the database we have, stored in
+// `SYNC_DIR/<repo>.db.state`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+struct Validators {
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+pub fn db_path(repo: &str) -> PathBuf {
+    SYNC_DIR.join(format!("{}.db", repo))
+}
+
+pub fn index_path(repo: &str) -> PathBuf {
+    SYNC_DIR.join(format!("{}.idx", repo))
+}
+
+/// The index of a synced repo, rebuilt from `<repo>.db` when the database
+/// is newer, e.g. after it was replaced by hand.
+pub fn open_repo(name: &str) -> Result<Index, String> {
+    let db = db_path(name);
+    let index = index_path(name);
+    let modified = |p: &Path| p.metadata().and_then(|m| m.modified()).ok();
+    if modified(&index).is_some() && modified(&index) >= modified(&db) {
+        if let Ok(index) = Index::open(&index) {
+            return Ok(index);
+        }
+    }
+    let data = read_db(&db)?;
+    Index::import(&data, &index).map_err(|e| format!("{}: {}", index.display(), e))
+}
+
+fn read_db(path: &Path) -> Result<BinRepo, String> {
+    File::open(path)
+        .map_err(|e| e.to_string())
+        .and_then(|f| serde_yaml::from_reader::<_, BinRepo>(f).map_err(|e| e.to_string()))
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+pub fn db_address(repo: &RepoMeta) -> Result<String, String> {
+    let base = Url::parse(&repo.static_address).map_err(|e| e.to_string())?;
+    let address = base.join(&repo.name).map_err(|e| e.to_string())?;
+    Ok(format!("{}.db", address))
+}
+
+/// Fetch the database of `repo` unless the server says ours is current.
+/// The new database is downloaded next to the old one, verified and parsed
+/// before it replaces it, so an interrupted sync leaves the old one intact.
+pub async fn sync_repo(repo: &RepoMeta, keyring: &Keyring, force: bool) -> SyncStatus {
+    match fetch(repo, keyring, force).await {
+        Ok(Some(data)) => SyncStatus::Updated(data),
+        Ok(None) => SyncStatus::UpToDate,
+        Err(e) => SyncStatus::Failed(e),
+    }
+}
+
+async fn fetch(repo: &RepoMeta, keyring: &Keyring, force: bool) -> Result<Option<Index>, String> {
+    let target = db_path(&repo.name);
+    let state_file = suffixed(&target, ".state");
+    let address = db_address(repo)?;
+
+    let mut request = reqwest::Client::new().get(&address);
+    if target.exists() && !force {
+        let old: Validators = File::open(&state_file)
+            .ok()
+            .and_then(|f| serde_yaml::from_reader(f).ok())
+            .unwrap_or_default();
+        if let Some(etag) = &old.etag {
+            request = request.header(header::IF_NONE_MATCH, etag);
+        }
+        if let Some(date) = &old.last_modified {
+            request = request.header(header::IF_MODIFIED_SINCE, date);
+        }
+    }
+    let res = request.send().await.map_err(|e| e.to_string())?;
+    if res.status() == StatusCode::NOT_MODIFIED {
+        return Ok(None);
+    }
+    if !res.status().is_success() {
+        return Err(format!("{}: {}", address, res.status()));
+    }
+    let header_value = |name: header::HeaderName| {
+        res.headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string())
+    };
+    let validators = Validators {
+        etag: header_value(header::ETAG),
+        last_modified: header_value(header::LAST_MODIFIED),
+    };
+    let body = res.bytes().await.map_err(|e| e.to_string())?;
+
+    let part = suffixed(&target, ".part");
+    let verified = match fs::write(&part, &body) {
+        Ok(_) => verify_download(keyring, repo.signatures, &part, &address).await,
+        Err(e) => Err(e.to_string()),
+    };
+    let parsed = verified.and_then(|_| read_db(&part));
+    let data = match parsed {
+        Ok(data) => data,
+        Err(e) => {
+            fs::remove_file(&part).ok();
+            fs::remove_file(sig_path(&part)).ok();
+            return Err(e);
+        }
+    };
+
+    // the database goes last, it is what the next run reads
+    fs::remove_file(&state_file).ok();
+    if sig_path(&part).exists() {
+        fs::rename(sig_path(&part), sig_path(&target)).map_err(|e| e.to_string())?;
+    }
+    fs::rename(&part, &target).map_err(|e| e.to_string())?;
+    write_state(&state_file, &validators).map_err(|e| e.to_string())?;
+    let index = index_path(&repo.name);
+    Index::import(&data, &index)
+        .map(Some)
+        .map_err(|e| format!("{}: {}", index.display(), e))
+}
+
+fn write_state(path: &Path, validators: &Validators) -> std::io::Result<()> {
+    let tmp = suffixed(path, ".part");
+    let file = File::create(&tmp)?;
+    serde_yaml::to_writer(file, validators).map_err(|e| std::io::Error::other(e.to_string()))?;
+    fs::rename(tmp, path)
+}
@@ -0,0 +1,128 @@
+// Packages, indexes and scratch paths for the unit tests.
+use crate::check::hash_file;
+use crate::utils::{prepare_bases, write_archive};
+use crate::{
+    Application, Dependency, FileEntry, FileKind, Index, Metadata, PkgVersion, CACHE_DIR, CONF_DIR,
+    LIB_DIR, LOCAL_DIR, ROOT_DIR,
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, MutexGuard, Once,
+    },
+    time::SystemTime,
+};
+
+/// A package with a `[epoch:]version[-release]` and run dependencies, the
+/// release defaults to 1.
+pub fn app(name: &str, version: &str, run: &[&str]) -> Application {
+    let v = PkgVersion::parse(version);
+    Application {
+        metadata: Metadata {
+            name: name.to_string(),
+            epoch: v.epoch,
+            version: v.version,
+            release: v.release.unwrap_or(1),
+            description: Some(format!("the {} package", name)),
+            ..Metadata::default()
+        },
+        dependencies: Some(Dependency {
+            run_dependencies: Some(strings(run)),
+            ..Dependency::default()
+        }),
+        ..Application::default()
+    }
+}
+
+/// `app` with `provides`, `conflicts` or `replaces` set to `names`.
+pub fn with(mut app: Application, field: &str, names: &[&str]) -> Application {
+    let deps = app.dependencies.get_or_insert_with(Dependency::default);
+    let list = match field {
+        "provides" => &mut deps.provides,
+        "conflicts" => &mut deps.conflicts,
+        "replaces" => &mut deps.replaces,
+        _ => panic!("no dependency list {}", field),
+    };
+    *list = Some(strings(names));
+    app
+}
+
+/// A path in the temp dir no other test uses.
+pub fn temp_path(name: &str) -> PathBuf {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    std::env::temp_dir().join(format!(
+        "pi-test-{}-{}-{}",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::SeqCst),
+        name
+    ))
+}
+
+/// An index of `apps`. Its file is gone already, the index keeps it open.
+pub fn index(apps: &[Application]) -> Index {
+    let path = temp_path("repo.idx");
+    Index::write(&path, SystemTime::now(), apps.iter()).unwrap();
+    let index = Index::open(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    index
+}
+
+/// `app` packed with `files`, `(path, content)` pairs, and the directories
+/// above them, into a `.app` in the temp dir.
+pub fn package(mut app: Application, files: &[(&str, &str)]) -> PathBuf {
+    let payload = temp_path("payload");
+    app.files.clear();
+    for (path, content) in files.iter() {
+        let mut dirs: Vec<&Path> = Path::new(path).ancestors().skip(1).collect();
+        dirs.pop();
+        for dir in dirs.into_iter().rev() {
+            let dir = dir.to_str().unwrap();
+            if app.file_entry(dir).is_none() {
+                fs::create_dir_all(payload.join(dir)).unwrap();
+                let mut entry = FileEntry::new(dir.to_string(), FileKind::Dir);
+                entry.mode = Some(0o755);
+                app.files.push(entry);
+            }
+        }
+        fs::write(payload.join(path), content).unwrap();
+        let mut entry = FileEntry::new(path.to_string(), FileKind::File);
+        entry.sha256 = hash_file(&payload.join(path)).unwrap();
+        entry.mode = Some(0o644);
+        app.files.push(entry);
+    }
+    let file = temp_path(&format!("{}.app", app.archive_name()));
+    write_archive(&app, &payload, &file).unwrap();
+    fs::remove_dir_all(&payload).ok();
+    file
+}
+
+/// Point ROOT_DIR at an empty scratch tree. Tests using it run one at a
+/// time, for as long as they hold on to what this returns. It has to come
+/// before anything reads ROOT_DIR.
+pub fn root() -> MutexGuard<'static, ()> {
+    static LOCK: Mutex<()> = Mutex::new(());
+    static INIT: Once = Once::new();
+    let guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    INIT.call_once(|| std::env::set_var("ROOT", temp_path("root")));
+    assert!(
+        ROOT_DIR.starts_with(std::env::temp_dir()),
+        "ROOT_DIR was read before testing::root"
+    );
+    if ROOT_DIR.exists() {
+        fs::remove_dir_all(ROOT_DIR.as_path()).unwrap();
+    }
+    prepare_bases(vec![
+        LIB_DIR.to_path_buf(),
+        LOCAL_DIR.to_path_buf(),
+        CACHE_DIR.to_path_buf(),
+        CONF_DIR.to_path_buf(),
+    ])
+    .unwrap();
+    guard
+}
+
+fn strings(list: &[&str]) -> Vec<String> {
+    list.iter().map(|s| s.to_string()).collect()
+}
@@ -0,0 +1,1028 @@
+use crate::statics::{LOCAL_DIR, LOCAL_INDEX, ROOT_DIR, TX_DIR};
+use crate::utils::{copy_metadata, move_path, prepare_base, stage_archive};
+use crate::{
+    check::hash_file, installed, Application, FileEntry, FileKind, Index, InstallReason, Trigger,
+};
+use colored::Colorize;
+use glob::Pattern;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{create_dir, remove_dir, remove_dir_all, remove_file, File},
+    io::{Error, ErrorKind, Result},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use walkdir::WalkDir;
+
+// One step of a commit. The whole plan is written to the journal before
+// anything touches ROOT_DIR so an interrupted run can be undone later.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum Action {
+    CreateDir(PathBuf),
+    Place {
+        source: PathBuf,
+        target: PathBuf,
+        backup: Option<PathBuf>,
+    },
+    Remove {
+        target: PathBuf,
+        backup: PathBuf,
+    },
+    RemoveDir(PathBuf),
+    // a file kept inside ROOT_DIR under another name, e.g. `.pisave`
+    Rename {
+        source: PathBuf,
+        target: PathBuf,
+        backup: Option<PathBuf>,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct StagedPackage {
+    pub app: Application,
+    pub payload: PathBuf,
+}
+
+// What removing one package did, shown once the transaction is done.
+#[derive(Clone, Debug, Default)]
+pub struct Removal {
+    pub name: String,
+    pub files: usize,
+    pub directories: usize,
+    // files that were already gone
+    pub missing: usize,
+    // directories left behind because something else is in them
+    pub kept: usize,
+}
+
+impl std::fmt::Display for Removal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: removed {} files, {} directories",
+            self.name, self.files, self.directories
+        )?;
+        if self.missing > 0 {
+            write!(f, ", {} files were already missing", self.missing)?;
+        }
+        if self.kept > 0 {
+            write!(f, ", kept {} directories that are not empty", self.kept)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct Transaction {
+    pub packages: Vec<StagedPackage>,
+    pub removing: Vec<Application>,
+    overwrite: Vec<Pattern>,
+    stage_dir: PathBuf,
+    local_dir: PathBuf,
+    backup_dir: PathBuf,
+    actions: Vec<Action>,
+    report: Vec<Removal>,
+    // modified config files that stay as they are during this transaction,
+    // and whether the incoming version changes them too
+    protected: HashMap<PathBuf, bool>,
+    // staged directory each new directory takes its owner and mode from
+    dir_sources: HashMap<PathBuf, PathBuf>,
+    notes: Vec<String>,
+}
+
+impl Transaction {
+    pub fn new() -> Result<Self> {
+        if Self::journal_file().exists() {
+            return Err(Error::other(
+                "An unfinished transaction was found, run pi again to recover it",
+            ));
+        }
+        if TX_DIR.exists() {
+            remove_dir_all(TX_DIR.as_path())?;
+        }
+        let stage_dir = TX_DIR.join("payload");
+        let local_dir = TX_DIR.join("local");
+        let backup_dir = TX_DIR.join("backup");
+        prepare_base(stage_dir.clone())?;
+        prepare_base(local_dir.clone())?;
+        prepare_base(backup_dir.clone())?;
+
+        Ok(Self {
+            packages: Vec::new(),
+            removing: Vec::new(),
+            overwrite: Vec::new(),
+            stage_dir,
+            local_dir,
+            backup_dir,
+            actions: Vec::new(),
+            report: Vec::new(),
+            protected: HashMap::new(),
+            dir_sources: HashMap::new(),
+            notes: Vec::new(),
+        })
+    }
+
+    fn journal_file() -> PathBuf {
+        TX_DIR.join("journal.yml")
+    }
+
+    /// Globs of paths that may be replaced even when they belong to another
+    /// package or to nobody, e.g. `/usr/lib/*`.
+    pub fn set_overwrite(&mut self, globs: &[String]) -> Result<()> {
+        for g in globs.iter() {
+            match Pattern::new(&format!("/{}", g.trim_start_matches('/'))) {
+                Ok(p) => self.overwrite.push(p),
+                Err(e) => return Err(Error::new(ErrorKind::InvalidInput, format!("{}: {}", g, e))),
+            }
+        }
+        Ok(())
+    }
+
+    fn may_overwrite(&self, rel: &Path) -> bool {
+        let path = Path::new("/").join(rel);
+        self.overwrite.iter().any(|p| p.matches_path(&path))
+    }
+
+    /// Unpack an `.app` into the staging area without touching ROOT_DIR.
+    pub fn stage(&mut self, app_file: &str) -> Result<&Application> {
+        let payload = self.stage_dir.join(self.packages.len().to_string());
+        let mut app = stage_archive(app_file, payload.to_str().unwrap())?;
+
+        // older manifests have no modes, remember them as unpacked so
+        // `pi check` can spot changes
+        for entry in app.files.iter_mut().filter(|e| e.mode.is_none()) {
+            if let Ok(meta) = payload.join(&entry.path).symlink_metadata() {
+                if meta.is_file() {
+                    entry.mode = Some(meta.permissions().mode() & 0o7777);
+                }
+            }
+        }
+        // older manifests list regular files only, add their directories
+        // so they can be removed with the package
+        let dirs: Vec<String> = WalkDir::new(&payload)
+            .min_depth(1)
+            .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_dir())
+            .map(|e| {
+                e.path()
+                    .strip_prefix(&payload)
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect();
+        for dir in dirs {
+            if app.file_entry(&dir).is_none() {
+                app.files.push(FileEntry::new(dir, FileKind::Dir));
+            }
+        }
+
+        if let Some(pos) = self
+            .packages
+            .iter()
+            .position(|p| p.app.metadata.name == app.metadata.name)
+        {
+            remove_dir_all(&self.packages[pos].payload)?;
+            self.packages.remove(pos);
+        }
+
+        // the installed version goes, so whatever the new one dropped goes
+        // with it
+        if let Some(old) = Application::is_installed(&app.metadata.name) {
+            self.remove(old);
+        }
+
+        let local = self.local_dir.join(&app.metadata.name);
+        prepare_base(local.clone())?;
+        match serde_yaml::to_writer(File::create(local.join("manifest.yml"))?, &app) {
+            Ok(_) => {}
+            Err(e) => return Err(Error::other(e.to_string())),
+        }
+
+        self.packages.push(StagedPackage { app, payload });
+        Ok(&self.packages.last().unwrap().app)
+    }
+
+    /// Record why a staged package is installed, committed with its manifest.
+    pub fn set_reason(&self, name: &str, reason: InstallReason) -> Result<()> {
+        std::fs::write(self.local_dir.join(name).join("reason"), reason.to_string())
+    }
+
+    /// Take an installed package out as part of this transaction.
+    pub fn remove(&mut self, app: Application) {
+        if !self
+            .removing
+            .iter()
+            .any(|r| r.metadata.name == app.metadata.name)
+        {
+            self.removing.push(app);
+        }
+    }
+
+    fn is_removing(&self, name: &str) -> bool {
+        self.removing.iter().any(|r| r.metadata.name == name)
+    }
+
+    fn is_staged(&self, name: &str) -> bool {
+        self.packages.iter().any(|p| p.app.metadata.name == name)
+    }
+
+    /// Make sure the staged packages can be placed next to each other and
+    /// over what is already installed.
+    pub fn check(&self) -> std::result::Result<(), Vec<String>> {
+        let mut conflicts: Vec<String> = Vec::new();
+        let mut owners: HashMap<PathBuf, &str> = HashMap::new();
+        let installed = installed_owners();
+
+        for pkg in self.packages.iter() {
+            let name = pkg.app.metadata.name.as_str();
+            for entry in WalkDir::new(&pkg.payload).min_depth(1) {
+                let entry = match entry {
+                    Ok(e) => e,
+                    Err(e) => {
+                        conflicts.push(e.to_string());
+                        continue;
+                    }
+                };
+                let rel = entry.path().strip_prefix(&pkg.payload).unwrap();
+                let target = ROOT_DIR.join(rel);
+                let on_disk = target.symlink_metadata();
+
+                if entry.file_type().is_dir() {
+                    if let Ok(meta) = on_disk {
+                        if !meta.is_dir() {
+                            conflicts.push(format!(
+                                "{}: /{} exists and is not a directory",
+                                name,
+                                rel.display()
+                            ));
+                        }
+                    }
+                    continue;
+                }
+
+                match owners.get(rel) {
+                    Some(owner) => conflicts.push(format!(
+                        "{} and {} both provide /{}",
+                        owner,
+                        name,
+                        rel.display()
+                    )),
+                    None => {
+                        owners.insert(rel.to_path_buf(), name);
+                    }
+                }
+
+                if let Ok(meta) = on_disk {
+                    if meta.is_dir() {
+                        conflicts.push(format!(
+                            "{}: /{} exists and is a directory",
+                            name,
+                            rel.display()
+                        ));
+                        continue;
+                    }
+                    if self.may_overwrite(rel) {
+                        continue;
+                    }
+                    match installed.get(rel) {
+                        Some(owner) if owner == name || self.is_removing(owner) => {}
+                        Some(owner) => conflicts.push(format!(
+                            "{}: /{} is owned by {}",
+                            name,
+                            rel.display(),
+                            owner
+                        )),
+                        None => conflicts.push(format!(
+                            "{}: /{} exists in filesystem",
+                            name,
+                            rel.display()
+                        )),
+                    }
+                }
+            }
+        }
+
+        if conflicts.is_empty() {
+            Ok(())
+        } else {
+            Err(conflicts)
+        }
+    }
+
+    fn plan_tree(
+        &mut self,
+        source_root: &Path,
+        target_root: &Path,
+        new_dirs: &mut HashSet<PathBuf>,
+        removed: &HashSet<PathBuf>,
+    ) -> Result<()> {
+        for entry in WalkDir::new(source_root).min_depth(1) {
+            let entry = entry?;
+            let rel = entry.path().strip_prefix(source_root).unwrap();
+            let target = target_root.join(rel);
+
+            if entry.file_type().is_dir() {
+                if target.symlink_metadata().is_err() && new_dirs.insert(target.clone()) {
+                    self.dir_sources
+                        .insert(target.clone(), entry.path().to_path_buf());
+                    self.actions.push(Action::CreateDir(target));
+                }
+            } else {
+                let target = match self.protected.get(&target) {
+                    // nothing new to merge, the local changes stay as they are
+                    Some(false) => continue,
+                    Some(true) => {
+                        let pinew = suffixed(&target, ".pinew");
+                        self.notes.push(format!(
+                            "{} was modified, the new version is {}",
+                            target.display(),
+                            pinew.display()
+                        ));
+                        pinew
+                    }
+                    None => target,
+                };
+                let backup = if target.symlink_metadata().is_ok() && !removed.contains(&target) {
+                    Some(self.backup_dir.join(self.actions.len().to_string()))
+                } else {
+                    None
+                };
+                self.actions.push(Action::Place {
+                    source: entry.path().to_path_buf(),
+                    target,
+                    backup,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    // Files go first, then the directories of every removed package that
+    // end up empty and that no remaining or incoming package uses.
+    fn plan_removal(&mut self, removed: &mut HashSet<PathBuf>) -> Result<()> {
+        let removing = self.removing.clone();
+        let mut kept_dirs: HashSet<String> = HashSet::new();
+        for app in installed().iter() {
+            if !self.is_removing(&app.metadata.name) {
+                kept_dirs.extend(
+                    app.files
+                        .iter()
+                        .filter(|e| e.kind == FileKind::Dir)
+                        .map(|e| e.path.clone()),
+                );
+            }
+        }
+        self.report.clear();
+
+        let mut reports: Vec<Removal> = Vec::new();
+        for app in removing.iter() {
+            let mut report = Removal {
+                name: app.metadata.name.clone(),
+                ..Removal::default()
+            };
+            let backup_files = app.backup_files();
+            // directories go below, once emptied
+            for entry in app.files.iter().filter(|e| e.kind != FileKind::Dir) {
+                let path = entry.path.clone();
+                let target = ROOT_DIR.join(&path);
+                if target.symlink_metadata().is_err() {
+                    report.missing += 1;
+                } else if self.protected.contains_key(&target) {
+                    continue;
+                } else if backup_files.contains(&path)
+                    && !self
+                        .packages
+                        .iter()
+                        .any(|p| p.payload.join(&path).is_file())
+                    && entry.kind == FileKind::File
+                    && hash_file(&target).is_ok_and(|h| h != entry.sha256)
+                {
+                    let pisave = suffixed(&target, ".pisave");
+                    let backup = if pisave.symlink_metadata().is_ok() {
+                        Some(self.backup_dir.join(self.actions.len().to_string()))
+                    } else {
+                        None
+                    };
+                    self.notes.push(format!(
+                        "{} was modified, saved as {}",
+                        target.display(),
+                        pisave.display()
+                    ));
+                    self.actions.push(Action::Rename {
+                        source: target,
+                        target: pisave,
+                        backup,
+                    });
+                } else if removed.insert(target.clone()) {
+                    let backup = self.backup_dir.join(self.actions.len().to_string());
+                    self.actions.push(Action::Remove { target, backup });
+                    report.files += 1;
+                }
+            }
+            reports.push(report);
+        }
+
+        let mut dirs: Vec<(usize, String)> = Vec::new();
+        for (i, app) in removing.iter().enumerate() {
+            for dir in app
+                .files
+                .iter()
+                .filter(|e| e.kind == FileKind::Dir && e.created)
+            {
+                let dir = &dir.path;
+                if !kept_dirs.contains(dir) && !dirs.iter().any(|(_, d)| d == dir) {
+                    dirs.push((i, dir.clone()));
+                }
+            }
+        }
+        // deepest first, so parents are looked at once their children are gone
+        dirs.sort_by(|a, b| {
+            let depth = |d: &str| Path::new(d).components().count();
+            depth(&b.1).cmp(&depth(&a.1)).then_with(|| b.1.cmp(&a.1))
+        });
+        for (i, dir) in dirs.into_iter() {
+            let target = ROOT_DIR.join(&dir);
+            let is_dir = target
+                .symlink_metadata()
+                .map(|m| m.is_dir())
+                .unwrap_or(false);
+            if !is_dir || self.packages.iter().any(|p| p.payload.join(&dir).is_dir()) {
+                continue;
+            }
+            let empty = std::fs::read_dir(&target)?
+                .all(|e| e.map(|e| removed.contains(&e.path())).unwrap_or(false));
+            if empty {
+                removed.insert(target.clone());
+                self.actions.push(Action::RemoveDir(target));
+                reports[i].directories += 1;
+            } else {
+                reports[i].kept += 1;
+            }
+        }
+        // upgraded packages are not reported as removed
+        self.report = reports
+            .into_iter()
+            .filter(|r| !self.is_staged(&r.name))
+            .collect();
+
+        for app in removing.iter() {
+            // an upgrade puts its new manifest in the same place
+            let upgraded = self.is_staged(&app.metadata.name);
+            let local = LOCAL_DIR.join(&app.metadata.name);
+            for entry in WalkDir::new(&local).min_depth(1).contents_first(true) {
+                let entry = entry?;
+                if entry.file_type().is_dir() {
+                    if upgraded {
+                        continue;
+                    }
+                    self.actions
+                        .push(Action::RemoveDir(entry.path().to_path_buf()));
+                } else {
+                    let backup = self.backup_dir.join(self.actions.len().to_string());
+                    removed.insert(entry.path().to_path_buf());
+                    self.actions.push(Action::Remove {
+                        target: entry.path().to_path_buf(),
+                        backup,
+                    });
+                }
+            }
+            if !upgraded {
+                self.actions.push(Action::RemoveDir(local));
+            }
+        }
+        Ok(())
+    }
+
+    // Config files of the packages being upgraded that were changed since
+    // they were installed, and whether the new version changes them as well.
+    fn find_protected(&self) -> HashMap<PathBuf, bool> {
+        let mut protected: HashMap<PathBuf, bool> = HashMap::new();
+        for pkg in self.packages.iter() {
+            let old = self
+                .removing
+                .iter()
+                .cloned()
+                .chain(Application::is_installed(&pkg.app.metadata.name))
+                .collect::<Vec<Application>>();
+            for path in pkg.app.backup_files().iter() {
+                let target = ROOT_DIR.join(path);
+                let sha = |app: &Application| app.file_entry(path).map(|e| e.sha256.clone());
+                let (original, new) = match (old.iter().find_map(sha), sha(&pkg.app)) {
+                    (Some(o), Some(n)) => (o, n),
+                    _ => continue,
+                };
+                if let Ok(current) = hash_file(&target) {
+                    if current != original && current != new {
+                        protected.insert(target, original != new);
+                    }
+                }
+            }
+        }
+        protected
+    }
+
+    fn plan(&mut self) -> Result<()> {
+        self.actions.clear();
+        self.dir_sources.clear();
+        self.notes.clear();
+        self.protected = self.find_protected();
+        let mut new_dirs: HashSet<PathBuf> = HashSet::new();
+        let mut removed: HashSet<PathBuf> = HashSet::new();
+        self.plan_removal(&mut removed)?;
+
+        let payloads: Vec<PathBuf> = self.packages.iter().map(|p| p.payload.clone()).collect();
+        for payload in payloads.iter() {
+            self.plan_tree(payload, ROOT_DIR.as_path(), &mut new_dirs, &removed)?;
+        }
+        self.mark_created(&new_dirs)?;
+        let local_dir = self.local_dir.clone();
+        self.plan_tree(&local_dir, LOCAL_DIR.as_path(), &mut new_dirs, &removed)?;
+        self.plan_index()
+    }
+
+    // Directories this transaction creates belong to the packages that
+    // bring them, and ones an upgrade keeps stay with the new version. The
+    // staged manifests are written again to record that.
+    fn mark_created(&mut self, new_dirs: &HashSet<PathBuf>) -> Result<()> {
+        let inherited: HashSet<String> = self
+            .removing
+            .iter()
+            .flat_map(|a| a.files.iter())
+            .filter(|e| e.kind == FileKind::Dir && e.created)
+            .map(|e| e.path.clone())
+            .collect();
+        for package in self.packages.iter_mut() {
+            for entry in package
+                .app
+                .files
+                .iter_mut()
+                .filter(|e| e.kind == FileKind::Dir)
+            {
+                entry.created = new_dirs.contains(&ROOT_DIR.join(&entry.path))
+                    || inherited.contains(&entry.path);
+            }
+            let manifest = self
+                .local_dir
+                .join(&package.app.metadata.name)
+                .join("manifest.yml");
+            if let Err(e) = serde_yaml::to_writer(File::create(manifest)?, &package.app) {
+                return Err(Error::other(e.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    // The local index is written in the staging area and swapped in like
+    // any other file, so a rollback brings the old one back as well.
+    fn plan_index(&mut self) -> Result<()> {
+        let mut apps: Vec<Application> = installed()
+            .into_iter()
+            .filter(|a| {
+                !self.is_removing(&a.metadata.name)
+                    && !self
+                        .packages
+                        .iter()
+                        .any(|p| p.app.metadata.name == a.metadata.name)
+            })
+            .collect();
+        apps.extend(self.packages.iter().map(|p| p.app.clone()));
+        let source = TX_DIR.join("local.idx");
+        Index::write(&source, SystemTime::now(), apps.iter())?;
+        let backup = if LOCAL_INDEX.exists() {
+            Some(self.backup_dir.join(self.actions.len().to_string()))
+        } else {
+            None
+        };
+        self.actions.push(Action::Place {
+            source,
+            target: LOCAL_INDEX.to_path_buf(),
+            backup,
+        });
+        Ok(())
+    }
+
+    fn write_journal(&self) -> Result<()> {
+        let file = File::create(Self::journal_file())?;
+        match serde_yaml::to_writer(file, &self.actions) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(Error::other(e.to_string())),
+        }
+    }
+
+    /// Move every staged file into place. Nothing is deleted from ROOT_DIR,
+    /// replaced files are only moved to the backup area until `finish`. The
+    /// journal goes once all is in place, from then on the transaction is
+    /// done and is no longer undone, whatever the hooks after it do.
+    pub fn commit(&mut self) -> Result<()> {
+        self.plan()?;
+        self.write_journal()?;
+
+        for action in self.actions.iter() {
+            match action {
+                Action::CreateDir(path) => create_dir(path)?,
+                Action::Place {
+                    source,
+                    target,
+                    backup,
+                } => {
+                    if let Some(backup) = backup {
+                        move_path(target, backup)?;
+                    }
+                    move_path(source, target)?;
+                }
+                Action::Remove { target, backup } => move_path(target, backup)?,
+                Action::RemoveDir(path) => remove_dir(path)?,
+                Action::Rename {
+                    source,
+                    target,
+                    backup,
+                } => {
+                    if let Some(backup) = backup {
+                        move_path(target, backup)?;
+                    }
+                    move_path(source, target)?;
+                }
+            }
+        }
+        // new directories get their owner and mode once filled, deepest
+        // first, so read-only ones don't get in the way
+        for action in self.actions.iter().rev() {
+            if let Action::CreateDir(path) = action {
+                if let Some(source) = self.dir_sources.get(path) {
+                    copy_metadata(source, path)?;
+                }
+            }
+        }
+        remove_file(Self::journal_file())
+    }
+
+    /// Undo every action of the commit, newest first.
+    pub fn rollback(&mut self) {
+        println!("{}", "ROLLING BACK".red().bold());
+        undo(&self.actions);
+        self.actions.clear();
+        if let Err(e) = remove_file(Self::journal_file()) {
+            if e.kind() != ErrorKind::NotFound {
+                eprintln!("{}", e.to_string().red());
+            }
+        }
+    }
+
+    /// Drop the journal, backups and staging area.
+    pub fn finish(self) -> Result<()> {
+        if Self::journal_file().exists() {
+            remove_file(Self::journal_file())?;
+        }
+        remove_dir_all(TX_DIR.as_path())
+    }
+
+    // Which hook of which package runs for this transaction, and with what
+    // arguments: "install", "upgrade" or "remove".
+    fn hook_targets(&self) -> Vec<(Application, &'static str, Vec<String>)> {
+        let version = |a: &Application| a.metadata.full_version();
+        let mut targets: Vec<(Application, &'static str, Vec<String>)> = Vec::new();
+        for app in self.removing.iter() {
+            if !self.is_staged(&app.metadata.name) {
+                targets.push((app.clone(), "remove", vec![version(app)]));
+            }
+        }
+        for pkg in self.packages.iter() {
+            match Application::is_installed(&pkg.app.metadata.name) {
+                Some(old) => targets.push((
+                    pkg.app.clone(),
+                    "upgrade",
+                    vec![version(&pkg.app), version(&old)],
+                )),
+                None => targets.push((pkg.app.clone(), "install", vec![version(&pkg.app)])),
+            }
+        }
+        targets
+    }
+
+    /// Check, commit and clean up, rolling everything back on failure.
+    pub fn run(mut self) -> std::result::Result<(), Vec<String>> {
+        if let Err(mut conflicts) = self.check() {
+            self.finish().ok();
+            conflicts.insert(0, String::from("FILE CONFLICTS"));
+            conflicts.push(String::from(
+                "Nothing was installed, use --overwrite <glob> to replace these files",
+            ));
+            return Err(conflicts);
+        }
+
+        let hooks = self.hook_targets();
+        for (app, kind, args) in hooks.iter() {
+            if let Some(h) = &app.hooks {
+                if let Err(e) = h.run(&format!("pre_{}", kind), args) {
+                    self.finish().ok();
+                    return Err(vec![format!("{}: {}", app.metadata.name, e)]);
+                }
+            }
+        }
+
+        if let Err(e) = self.commit() {
+            eprintln!("{}", e.to_string().red());
+            self.rollback();
+            self.finish().ok();
+            return Err(vec![e.to_string()]);
+        }
+        for (app, kind, args) in hooks.iter() {
+            if let Some(h) = &app.hooks {
+                if let Err(e) = h.run(&format!("post_{}", kind), args) {
+                    eprintln!("{}", format!("{}: {}", app.metadata.name, e).yellow());
+                }
+            }
+        }
+        let triggers: Vec<Trigger> = Trigger::load_all()
+            .into_iter()
+            .filter(|t| {
+                hooks
+                    .iter()
+                    .any(|(app, kind, _)| t.matches(kind, &app.file_paths()))
+            })
+            .collect();
+        for trigger in triggers.iter() {
+            if let Err(e) = trigger.run() {
+                eprintln!("{}", e.yellow());
+            }
+        }
+        self.report.iter().for_each(|r| println!("{}", r));
+        self.notes.iter().for_each(|n| println!("{}", n.yellow()));
+        match self.finish() {
+            Ok(_) => Ok(()),
+            Err(e) => Err(vec![e.to_string()]),
+        }
+    }
+
+    /// Undo a transaction that was interrupted while committing. One that
+    /// got as far as its hooks only leaves its backups behind, they go.
+    pub fn recover() -> Result<()> {
+        let journal = Self::journal_file();
+        if !journal.exists() {
+            if TX_DIR.exists() {
+                remove_dir_all(TX_DIR.as_path())?;
+            }
+            return Ok(());
+        }
+        println!("{}", "RECOVERING INTERRUPTED TRANSACTION".yellow().bold());
+        let actions: Vec<Action> = match serde_yaml::from_reader(File::open(&journal)?) {
+            Ok(a) => a,
+            Err(e) => return Err(Error::other(e.to_string())),
+        };
+        undo(&actions);
+        remove_file(journal)?;
+        remove_dir_all(TX_DIR.as_path())
+    }
+}
+
+// Map every file recorded in LOCAL_DIR to the package that installed it.
+fn installed_owners() -> HashMap<PathBuf, String> {
+    let mut owners: HashMap<PathBuf, String> = HashMap::new();
+    for app in installed().into_iter() {
+        for path in app.file_paths() {
+            owners.insert(PathBuf::from(path), app.metadata.name.clone());
+        }
+    }
+    owners
+}
+
+// Every step is checked against the filesystem, so this is safe to run on a
+// plan that was only partly applied.
+fn undo(actions: &[Action]) {
+    for action in actions.iter().rev() {
+        match action {
+            Action::CreateDir(path) => {
+                remove_dir(path).ok();
+            }
+            Action::Place {
+                source,
+                target,
+                backup,
+            } => {
+                if source.symlink_metadata().is_err() {
+                    if let Err(e) = remove_file(target) {
+                        if e.kind() != ErrorKind::NotFound {
+                            eprintln!("{}: {}", target.display(), e.to_string().red());
+                        }
+                    }
+                }
+                if let Some(backup) = backup {
+                    restore(backup, target);
+                }
+            }
+            Action::Remove { target, backup } => restore(backup, target),
+            Action::RemoveDir(path) => {
+                if path.symlink_metadata().is_err() {
+                    create_dir(path).ok();
+                }
+            }
+            Action::Rename {
+                source,
+                target,
+                backup,
+            } => {
+                if source.symlink_metadata().is_err() {
+                    restore(target, source);
+                }
+                if let Some(backup) = backup {
+                    restore(backup, target);
+                }
+            }
+        }
+    }
+}
+
+pub fn suffixed(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn restore(backup: &Path, target: &Path) {
+    if backup.symlink_metadata().is_ok() {
+        if let Err(e) = move_path(backup, target) {
+            eprintln!("{}: {}", target.display(), e.to_string().red());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{app, package, root};
+    use std::fs;
+
+    fn stage(t: &mut Transaction, file: &Path) {
+        t.stage(file.to_str().unwrap()).unwrap();
+    }
+
+    fn install(files: &[&Path]) -> std::result::Result<(), Vec<String>> {
+        let mut t = Transaction::new().unwrap();
+        files.iter().for_each(|f| stage(&mut t, f));
+        t.run()
+    }
+
+    fn read(path: &str) -> Option<String> {
+        fs::read_to_string(ROOT_DIR.join(path)).ok()
+    }
+
+    fn version(name: &str) -> Option<String> {
+        Application::is_installed(name).map(|a| a.metadata.full_version())
+    }
+
+    fn with_backup(mut app: Application, paths: &[&str]) -> Application {
+        app.backup = Some(paths.iter().map(|p| p.to_string()).collect());
+        app
+    }
+
+    // a 1.0 with a file 2.0 drops, and a 2.0 that cannot be placed since
+    // /opt/a is a file, not a directory
+    fn broken_upgrade() -> Transaction {
+        let old = package(
+            app("a", "1.0", &[]),
+            &[("usr/bin/a", "a 1.0"), ("usr/lib/a.so", "lib 1.0")],
+        );
+        install(&[&old]).unwrap();
+        fs::write(ROOT_DIR.join("opt"), "in the way").unwrap();
+        let new = package(
+            app("a", "2.0", &[]),
+            &[
+                ("usr/bin/a", "a 2.0"),
+                ("usr/share/a/data", "data"),
+                ("opt/a/x", "x"),
+            ],
+        );
+        let mut t = Transaction::new().unwrap();
+        stage(&mut t, &new);
+        t
+    }
+
+    fn assert_untouched() {
+        assert_eq!(read("usr/bin/a").as_deref(), Some("a 1.0"));
+        assert_eq!(read("usr/lib/a.so").as_deref(), Some("lib 1.0"));
+        assert_eq!(read("opt").as_deref(), Some("in the way"));
+        assert!(!ROOT_DIR.join("usr/share/a").exists());
+        assert_eq!(version("a").as_deref(), Some("1.0-1"));
+        assert_eq!(installed().len(), 1);
+        assert!(!Transaction::journal_file().exists());
+    }
+
+    #[test]
+    fn stages_and_commits() {
+        let _root = root();
+        let file = package(app("a", "1.0", &[]), &[("usr/bin/a", "a 1.0")]);
+        let mut t = Transaction::new().unwrap();
+        stage(&mut t, &file);
+        assert!(read("usr/bin/a").is_none());
+        assert!(version("a").is_none());
+
+        t.run().unwrap();
+        assert_eq!(read("usr/bin/a").as_deref(), Some("a 1.0"));
+        assert_eq!(version("a").as_deref(), Some("1.0-1"));
+        assert_eq!(installed().len(), 1);
+        assert!(!TX_DIR.exists());
+    }
+
+    #[test]
+    fn refuses_conflicting_files() {
+        let _root = root();
+        let a = package(app("a", "1.0", &[]), &[("usr/bin/tool", "a")]);
+        let b = package(app("b", "1.0", &[]), &[("usr/bin/tool", "b")]);
+        let errors = install(&[&a, &b]).unwrap_err();
+        assert_eq!(errors[0], "FILE CONFLICTS");
+        assert!(errors.contains(&String::from("a and b both provide /usr/bin/tool")));
+        assert!(read("usr/bin/tool").is_none());
+
+        fs::create_dir_all(ROOT_DIR.join("usr/bin")).unwrap();
+        fs::write(ROOT_DIR.join("usr/bin/tool"), "mine").unwrap();
+        let errors = install(&[&a]).unwrap_err();
+        assert!(errors.contains(&String::from("a: /usr/bin/tool exists in filesystem")));
+        assert_eq!(read("usr/bin/tool").as_deref(), Some("mine"));
+
+        let mut t = Transaction::new().unwrap();
+        t.set_overwrite(&[String::from("usr/bin/*")]).unwrap();
+        stage(&mut t, &a);
+        t.run().unwrap();
+        assert_eq!(read("usr/bin/tool").as_deref(), Some("a"));
+
+        let errors = install(&[&b]).unwrap_err();
+        assert!(errors.contains(&String::from("b: /usr/bin/tool is owned by a")));
+        assert_eq!(read("usr/bin/tool").as_deref(), Some("a"));
+        assert!(version("b").is_none());
+    }
+
+    #[test]
+    fn rolls_back_a_failed_commit() {
+        let _root = root();
+        let t = broken_upgrade();
+        assert!(t.run().is_err());
+        assert_untouched();
+        assert!(!TX_DIR.exists());
+    }
+
+    #[test]
+    fn recovers_an_interrupted_commit() {
+        let _root = root();
+        let mut t = broken_upgrade();
+        assert!(t.commit().is_err());
+        // killed before it could roll back
+        drop(t);
+        assert!(Transaction::journal_file().exists());
+        assert!(Transaction::new().is_err());
+
+        Transaction::recover().unwrap();
+        assert_untouched();
+        assert!(!TX_DIR.exists());
+    }
+
+    #[test]
+    fn keeps_a_finished_commit() {
+        let _root = root();
+        let old = package(app("a", "1.0", &[]), &[("usr/bin/a", "a 1.0")]);
+        install(&[&old]).unwrap();
+        let new = package(app("a", "2.0", &[]), &[("usr/bin/a", "a 2.0")]);
+        let mut t = Transaction::new().unwrap();
+        stage(&mut t, &new);
+        t.commit().unwrap();
+        // killed while its hooks ran
+        drop(t);
+        assert!(!Transaction::journal_file().exists());
+
+        Transaction::recover().unwrap();
+        assert_eq!(read("usr/bin/a").as_deref(), Some("a 2.0"));
+        assert_eq!(version("a").as_deref(), Some("2.0-1"));
+        assert!(!TX_DIR.exists());
+    }
+
+    #[test]
+    fn keeps_modified_config_files() {
+        let _root = root();
+        let conf = |version: &str, content: &str| {
+            let app = with_backup(app("c", version, &[]), &["etc/c.conf"]);
+            package(app, &[("etc/c.conf", content)])
+        };
+        install(&[&conf("1.0", "port 1")]).unwrap();
+        fs::write(ROOT_DIR.join("etc/c.conf"), "port 2").unwrap();
+
+        // the same config in the new version, the changes stay in place
+        install(&[&conf("1.1", "port 1")]).unwrap();
+        assert_eq!(read("etc/c.conf").as_deref(), Some("port 2"));
+        assert!(read("etc/c.conf.pinew").is_none());
+
+        install(&[&conf("2.0", "port 3")]).unwrap();
+        assert_eq!(read("etc/c.conf").as_deref(), Some("port 2"));
+        assert_eq!(read("etc/c.conf.pinew").as_deref(), Some("port 3"));
+
+        let mut t = Transaction::new().unwrap();
+        t.remove(Application::is_installed("c").unwrap());
+        t.run().unwrap();
+        assert!(read("etc/c.conf").is_none());
+        assert_eq!(read("etc/c.conf.pisave").as_deref(), Some("port 2"));
+        assert!(version("c").is_none());
+    }
+}
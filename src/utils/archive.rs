@@ -1,49 +1,271 @@
-use super::compress::compress_zstd;
-use crate::statics::LOCAL_DIR;
-use crate::utils::prepare::prepare_base;
-use crate::{Application, BuildFile};
+use crate::statics::SUFFIX_APP;
+use crate::utils::{prepare::prepare_base, set_metadata};
+use crate::{Application, FileEntry, FileKind};
 use std::{
-    fs::{create_dir_all, remove_file, File},
-    io::{Read, Result},
+    collections::HashSet,
+    fs::{create_dir_all, hard_link, read_link, remove_file, File},
+    io::{empty, Error, ErrorKind, Read, Result},
+    os::unix::fs::{symlink, MetadataExt},
     path::{Path, PathBuf},
 };
-use tar::Archive;
+use tar::{Archive, EntryType, Header};
 use zip::ZipArchive;
 
+// A .app is a zstd compressed tar. Its metadata comes first under the
+// reserved `.PI/` prefix, starting with `.PI/format`, the version of this
+// layout, then `.PI/manifest.yml`. Other names under `.PI/` are kept for
+// later metadata and skipped by this version. The payload follows, rooted
+// at `/`. Packages from before this layout have no `.PI/format` and carry
+// their manifest as `manifest.yml` at the root, they read as format 0.
+// Format 2 lists every payload entry in the manifest with its type, owner
+// and mode, and the payload is packed and unpacked exactly as listed, a
+// payload entry the manifest leaves out is refused.
+pub const PACKAGE_FORMAT: u32 = 2;
+pub const META_DIR: &str = ".PI";
+const FORMAT_FILE: &str = ".PI/format";
+const MANIFEST_FILE: &str = ".PI/manifest.yml";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Member {
+    Format,
+    Manifest,
+    Meta,
+    Payload,
+}
+
+// Tells the members of a package apart as they stream by.
+struct Layout {
+    format: Option<u32>,
+}
+
+impl Layout {
+    fn new() -> Self {
+        Self { format: None }
+    }
+
+    fn member(&mut self, path: &str) -> Member {
+        if self.format.is_none() {
+            if path == FORMAT_FILE {
+                return Member::Format;
+            }
+            self.format = Some(0);
+        }
+        if self.format == Some(0) {
+            return match path {
+                "manifest.yml" => Member::Manifest,
+                _ => Member::Payload,
+            };
+        }
+        match path {
+            MANIFEST_FILE => Member::Manifest,
+            p if p.trim_end_matches('/') == META_DIR || p.starts_with(".PI/") => Member::Meta,
+            _ => Member::Payload,
+        }
+    }
+
+    // Take the format from `.PI/format`, refusing layouts newer than ours.
+    fn read_format<R: Read>(&mut self, entry: &mut R) -> Result<()> {
+        let mut buf: String = String::new();
+        entry.read_to_string(&mut buf)?;
+        let format: u32 = buf.trim().parse().map_err(|_| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("invalid package format {}", buf.trim()),
+            )
+        })?;
+        if format > PACKAGE_FORMAT {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "package format {} needs a newer pi, this one reads up to {}",
+                    format, PACKAGE_FORMAT
+                ),
+            ));
+        }
+        self.format = Some(format);
+        Ok(())
+    }
+}
+
+fn parse_manifest<R: Read>(entry: &mut R) -> Result<Application> {
+    let mut buf: String = String::new();
+    entry.read_to_string(&mut buf)?;
+    serde_yaml::from_str(&buf).map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))
+}
+
 pub fn extract_archive(arg_file: &str, dest: &str) -> Result<()> {
+    unpack_archive(Archive::new(File::open(arg_file)?), dest)
+}
+
+// Unpack a tar stream, e.g. a source tarball, into `dest`.
+pub fn unpack_archive<R: Read>(mut a: Archive<R>, dest: &str) -> Result<()> {
     let dest_path: PathBuf = PathBuf::from(&dest);
     if !dest_path.exists() {
-        create_dir_all(&dest).unwrap();
+        create_dir_all(dest).unwrap();
+    }
+
+    for file in a.entries()? {
+        let mut f = file?;
+        let p: String = f.path()?.clone().to_str().unwrap().to_string();
+        f.set_unpack_xattrs(true);
+        f.set_preserve_permissions(true);
+        f.unpack(Path::new(dest).join(&p))?;
     }
+    Ok(())
+}
+
+// Unpack the payload of a `.app` into `dest` and hand back its manifest
+// instead of registering it in LOCAL_DIR. The zstd stream goes straight
+// into tar, nothing is written besides the payload.
+pub fn stage_archive(app_file: &str, dest: &str) -> Result<Application> {
+    prepare_base(PathBuf::from(dest))?;
 
-    let file = File::open(arg_file)?;
-    let mut a = Archive::new(file);
-
-    match a.entries() {
-        Ok(all_entries) => Ok(for file in all_entries {
-            let mut f = file?;
-            let p: String = f.path()?.clone().to_str().unwrap().to_string();
-            match p.as_str() {
-                "manifest.yml" => {
-                    let mut buf: String = String::new();
-                    f.read_to_string(&mut buf).unwrap();
-                    let manifest: Application = serde_yaml::from_str(&buf).unwrap();
-                    let destname = LOCAL_DIR.join(&manifest.metadata.name);
-                    prepare_base(destname.clone()).unwrap();
-                    serde_yaml::to_writer(File::create(destname.join(&p)).unwrap(), &manifest)
-                        .unwrap();
+    let mut a = Archive::new(zstd::Decoder::new(File::open(app_file)?)?);
+    let mut layout = Layout::new();
+    let mut manifest: Option<Application> = None;
+    // paths the manifest lists, from format 2 on nothing else is unpacked
+    let mut listed: Option<HashSet<String>> = None;
+
+    for file in a.entries()? {
+        let mut f = file?;
+        let p: String = f.path()?.to_str().unwrap().to_string();
+        match layout.member(&p) {
+            Member::Format => layout.read_format(&mut f)?,
+            Member::Manifest => {
+                let m = parse_manifest(&mut f)?;
+                if layout.format >= Some(2) {
+                    listed = Some(m.file_paths().into_iter().collect());
                 }
-                _ => {
-                    f.set_unpack_xattrs(true);
-                    f.set_preserve_permissions(true);
-                    f.unpack(Path::new(dest).join(&p))?;
+                manifest = Some(m);
+            }
+            Member::Meta => {}
+            Member::Payload => {
+                if layout.format >= Some(2) {
+                    let path = p.trim_start_matches("./").trim_end_matches('/');
+                    if !listed.as_ref().is_some_and(|l| l.contains(path)) {
+                        return Err(Error::new(
+                            ErrorKind::InvalidData,
+                            format!("{} is in the package but not in its manifest", path),
+                        ));
+                    }
                 }
-            };
-        }),
-        Err(e) => return Err(e),
+                f.set_unpack_xattrs(true);
+                f.set_preserve_permissions(true);
+                f.unpack_in(dest)?;
+            }
+        }
+    }
+
+    match manifest {
+        Some(m) => {
+            restore_entries(Path::new(dest), &m.files)?;
+            Ok(m)
+        }
+        None => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("{} has no manifest.yml", app_file),
+        )),
     }
 }
 
+// Make the unpacked payload in `dest` what the manifest lists: links where
+// tar left them out or got them wrong, then owners and modes, deepest first
+// so read-only directories are done last. Nothing is done through a symlink,
+// which could lead out of `dest`.
+fn restore_entries(dest: &Path, files: &[FileEntry]) -> Result<()> {
+    let invalid = |entry: &FileEntry, what: &str| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("{} {} in the package", entry.path, what),
+        )
+    };
+    for entry in files.iter() {
+        let path = inside(dest, &entry.path)?;
+        match (entry.kind, &entry.target) {
+            (FileKind::Symlink, Some(target))
+                if read_link(&path).ok().as_deref() != Some(Path::new(target)) =>
+            {
+                if path.symlink_metadata().is_ok() {
+                    remove_file(&path)?;
+                }
+                symlink(target, &path)?;
+            }
+            (FileKind::Hardlink, Some(target)) => {
+                let first = inside(dest, target)?;
+                let source = match first.symlink_metadata() {
+                    Ok(m) if m.is_file() => m,
+                    _ => return Err(invalid(entry, "links to a file that is missing")),
+                };
+                let linked = path
+                    .symlink_metadata()
+                    .is_ok_and(|m| m.dev() == source.dev() && m.ino() == source.ino());
+                if !linked {
+                    if path.symlink_metadata().is_ok() {
+                        remove_file(&path)?;
+                    }
+                    hard_link(&first, &path)?;
+                }
+            }
+            (FileKind::Dir, _) if path.symlink_metadata().is_err() => create_dir_all(&path)?,
+            _ => {}
+        }
+        let is_kind = path.symlink_metadata().is_ok_and(|m| match entry.kind {
+            FileKind::File | FileKind::Hardlink => m.is_file(),
+            FileKind::Dir => m.is_dir(),
+            FileKind::Symlink => m.file_type().is_symlink(),
+        });
+        if !is_kind {
+            return Err(invalid(entry, "is missing or of another type"));
+        }
+    }
+    for entry in files.iter().rev() {
+        let mode = match entry.kind {
+            FileKind::Symlink => None,
+            _ => entry.mode,
+        };
+        set_metadata(&dest.join(&entry.path), entry.uid, entry.gid, mode)?;
+    }
+    Ok(())
+}
+
+// `dest` joined with a path from a manifest, none of whose parents may be a
+// symlink.
+fn inside(dest: &Path, path: &str) -> Result<PathBuf> {
+    let mut dir = dest.to_path_buf();
+    for part in Path::new(path).parent().into_iter().flat_map(|p| p.iter()) {
+        dir.push(part);
+        if dir
+            .symlink_metadata()
+            .is_ok_and(|m| m.file_type().is_symlink())
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("{} is below a symlink in the package", path),
+            ));
+        }
+    }
+    Ok(dest.join(path))
+}
+
+/// Read the manifest of a `.app` without unpacking anything else.
+pub fn read_manifest(app_file: &Path) -> Result<Application> {
+    let mut a = Archive::new(zstd::Decoder::new(File::open(app_file)?)?);
+    let mut layout = Layout::new();
+    for file in a.entries()? {
+        let mut f = file?;
+        let p: String = f.path()?.to_str().unwrap_or_default().to_string();
+        match layout.member(&p) {
+            Member::Format => layout.read_format(&mut f)?,
+            Member::Manifest => return parse_manifest(&mut f),
+            _ => {}
+        }
+    }
+    Err(Error::new(
+        ErrorKind::InvalidData,
+        format!("{} has no manifest.yml", app_file.display()),
+    ))
+}
+
 pub fn extract_zip(arg_file: &str, dest: &str) -> Result<()> {
     let mut decoder = {
         let file = File::open(arg_file)?;
@@ -54,17 +276,92 @@ pub fn extract_zip(arg_file: &str, dest: &str) -> Result<()> {
     Ok(())
 }
 
-pub fn create_archive(data: &BuildFile, path: PathBuf) {
-    let archive_name = data.archive_name();
-    let pkgf = File::create(&archive_name).unwrap();
-    let mut tar = tar::Builder::new(pkgf);
+/// Pack the payload in `path` with the manifest of `app` into
+/// `<archive name>.app`, metadata first. Only what the manifest lists is
+/// packed, owned and with the mode it says.
+pub fn create_archive(app: &Application, path: PathBuf) -> Result<()> {
+    write_archive(app, &path, Path::new(&(app.archive_name() + &SUFFIX_APP)))
+}
+
+/// `create_archive` into the file `target`.
+pub fn write_archive(app: &Application, path: &Path, target: &Path) -> Result<()> {
+    if path.join(META_DIR).exists() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "{} is reserved for package metadata",
+                path.join(META_DIR).display()
+            ),
+        ));
+    }
+    let manifest = serde_yaml::to_string(app).map_err(|e| Error::other(e.to_string()))?;
+    let mut tar = tar::Builder::new(zstd::Encoder::new(File::create(target)?, 1)?);
+
+    append_meta(
+        &mut tar,
+        FORMAT_FILE,
+        format!("{}\n", PACKAGE_FORMAT).as_bytes(),
+    )?;
+    append_meta(&mut tar, MANIFEST_FILE, manifest.as_bytes())?;
+    for entry in app.files.iter() {
+        append_entry(&mut tar, path, entry)?;
+    }
+
+    tar.into_inner()?.finish()?;
+    Ok(())
+}
+
+fn append_entry<W: std::io::Write>(
+    tar: &mut tar::Builder<W>,
+    root: &Path,
+    entry: &FileEntry,
+) -> Result<()> {
+    let source = root.join(&entry.path);
+    let meta = source.symlink_metadata()?;
+    let mut header = Header::new_gnu();
+    header.set_size(0);
+    // without a recorded mode the file keeps its own, symlinks have 0777
+    header.set_mode(entry.mode.unwrap_or(meta.mode() & 0o7777));
+    header.set_uid(entry.uid.unwrap_or(0) as u64);
+    header.set_gid(entry.gid.unwrap_or(0) as u64);
+    header.set_mtime(meta.mtime().max(0) as u64);
 
-    tar.follow_symlinks(false);
-    match tar.append_dir_all(".", path) {
-        Ok(_) => {}
-        Err(e) => println!("{:#?}", e),
+    match (entry.kind, &entry.target) {
+        (FileKind::File, _) => {
+            header.set_entry_type(EntryType::Regular);
+            header.set_size(meta.len());
+            tar.append_data(&mut header, &entry.path, File::open(&source)?)
+        }
+        (FileKind::Dir, _) => {
+            header.set_entry_type(EntryType::Directory);
+            tar.append_data(&mut header, &entry.path, empty())
+        }
+        (FileKind::Symlink, Some(target)) => {
+            header.set_entry_type(EntryType::Symlink);
+            tar.append_link(&mut header, &entry.path, target)
+        }
+        (FileKind::Hardlink, Some(target)) => {
+            header.set_entry_type(EntryType::Link);
+            tar.append_link(&mut header, &entry.path, target)
+        }
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("{} is a link without a target", entry.path),
+        )),
     }
+}
 
-    compress_zstd(&archive_name).unwrap();
-    remove_file(&archive_name).unwrap();
+fn append_meta<W: std::io::Write>(
+    tar: &mut tar::Builder<W>,
+    name: &str,
+    data: &[u8],
+) -> Result<()> {
+    let mut header = Header::new_gnu();
+    header.set_entry_type(EntryType::Regular);
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_mtime(0);
+    tar.append_data(&mut header, name, data)
 }
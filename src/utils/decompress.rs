@@ -1,4 +1,4 @@
-use crate::utils::{extract_archive, extract_zip};
+use crate::utils::{extract_zip, unpack_archive};
 use bzip2::read::BzDecoder;
 use colored::Colorize;
 use flate2::read::GzDecoder;
@@ -8,6 +8,7 @@ use std::{
     io::*,
     path::PathBuf,
     str,
+    time::Duration,
 };
 use tar::Archive;
 use xz2::read::XzDecoder;
@@ -28,11 +29,12 @@ pub fn decompress_all(source: &str, dest: &str) -> Result<()> {
             let mut archive = Archive::new(tar);
 
             let pb = ProgressBar::new_spinner();
-            pb.enable_steady_tick(100);
+            pb.enable_steady_tick(Duration::from_millis(100));
             pb.set_style(
                 ProgressStyle::default_spinner()
                     .tick_chars("/|\\- ")
-                    .template("{spinner:.green.bold} Extracting: {wide_msg}"),
+                    .template("{spinner:.green.bold} Extracting: {wide_msg}")
+                    .unwrap(),
             );
 
             for file in archive.entries().unwrap() {
@@ -62,11 +64,12 @@ pub fn decompress_all(source: &str, dest: &str) -> Result<()> {
             let mut archive = Archive::new(tar);
 
             let pb = ProgressBar::new_spinner();
-            pb.enable_steady_tick(100);
+            pb.enable_steady_tick(Duration::from_millis(100));
             pb.set_style(
                 ProgressStyle::default_spinner()
                     .tick_chars("/|\\- ")
-                    .template("{spinner:.green.bold} Extracting: {wide_msg}"),
+                    .template("{spinner:.green.bold} Extracting: {wide_msg}")
+                    .unwrap(),
             );
 
             for file in archive.entries().unwrap() {
@@ -92,11 +95,12 @@ pub fn decompress_all(source: &str, dest: &str) -> Result<()> {
             let mut archive = Archive::new(tar);
 
             let pb = ProgressBar::new_spinner();
-            pb.enable_steady_tick(100);
+            pb.enable_steady_tick(Duration::from_millis(100));
             pb.set_style(
                 ProgressStyle::default_spinner()
                     .tick_chars("/|\\- ")
-                    .template("{spinner:.green.bold} Extracting: {wide_msg}"),
+                    .template("{spinner:.green.bold} Extracting: {wide_msg}")
+                    .unwrap(),
             );
 
             for file in archive.entries().unwrap() {
@@ -117,29 +121,9 @@ pub fn decompress_all(source: &str, dest: &str) -> Result<()> {
             pb.finish();
         }
         "zip" => extract_zip(file_path.to_str().unwrap(), dest).unwrap(),
-        "zst" => {
-            let mut decoder = {
-                let file = File::open(&file_path)?;
-                Decoder::new(file)?
-            };
-            let tar_path = source.to_string().trim_end_matches(".zst").to_string();
-            let mut target = File::create(&tar_path)?;
-            copy(&mut decoder, &mut target)?;
-            extract_archive(&tar_path, dest)?;
-        }
+        "zst" => unpack_archive(Archive::new(Decoder::new(File::open(&file_path)?)?), dest)?,
         _ => {}
     }
 
     Ok(())
 }
-
-pub fn decompress_zstd(source: &str) -> Result<()> {
-    let mut decoder = {
-        let file = File::open(&source)?;
-        Decoder::new(file)?
-    };
-    let tar_path = source.to_string().trim_end_matches(".app").to_string();
-    let mut target = File::create(&tar_path)?;
-    copy(&mut decoder, &mut target)?;
-    Ok(())
-}
@@ -1,16 +1,25 @@
 use anyhow::anyhow;
 use git2::{ErrorCode, Repository};
-use indicatif::{ProgressBar, ProgressStyle};
-use reqwest::{header, redirect::Policy};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use reqwest::{header, redirect::Policy, Client, StatusCode};
 use std::fs::remove_dir_all;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use tokio::{fs, io::AsyncWriteExt};
 
-pub async fn download_http(
-    file_path: &str,
-    app_name: &str,
-    address: &str,
-) -> Result<(), anyhow::Error> {
+const BAR: &str = "{wide_msg}[{bar:60.green/blue}] {percent:>3}% {total_bytes:>10}";
+
+// A file for `download_all`.
+#[derive(Clone, Debug)]
+pub struct Download {
+    pub name: String,
+    pub address: String,
+    pub file: PathBuf,
+    // known from the repo database, saves a HEAD request
+    pub size: Option<u64>,
+}
+
+fn client() -> Result<Client, reqwest::Error> {
     let custom = Policy::custom(|attempt| {
         if attempt.previous().len() > 5 {
             attempt.error("too many redirects")
@@ -21,36 +30,45 @@ pub async fn download_http(
             attempt.follow()
         }
     });
-    let client = reqwest::Client::builder().redirect(custom).build()?;
-
-    let total_size = {
-        let resp = client.head(address).send().await?;
-        if resp.status().is_success() {
-            resp.headers()
-                .get(header::CONTENT_LENGTH)
-                .and_then(|ct_len| ct_len.to_str().ok())
-                .and_then(|ct_len| ct_len.parse().ok())
-                .unwrap_or(0)
-        } else {
-            return Err(anyhow!(
-                "Couldn't download URL: {}. Error: {:?}",
-                address,
-                resp.status(),
-            ));
-        }
-    };
+    Client::builder().redirect(custom).build()
+}
+
+fn bar_style(name: &str) -> ProgressStyle {
+    ProgressStyle::default_bar()
+        .template(&format!("=> {app} {bar}", app = name, bar = BAR))
+        .unwrap()
+        .progress_chars("#>-")
+}
+
+async fn content_length(client: &Client, address: &str) -> Result<u64, anyhow::Error> {
+    let resp = client.head(address).send().await?;
+    if resp.status().is_success() {
+        Ok(resp
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|ct_len| ct_len.to_str().ok())
+            .and_then(|ct_len| ct_len.parse().ok())
+            .unwrap_or(0))
+    } else {
+        Err(anyhow!(
+            "Couldn't download URL: {}. Error: {:?}",
+            address,
+            resp.status(),
+        ))
+    }
+}
+
+pub async fn download_http(
+    file_path: &str,
+    app_name: &str,
+    address: &str,
+) -> Result<(), anyhow::Error> {
+    let client = client()?;
+    let total_size = content_length(&client, address).await?;
 
     let mut request = client.get(address);
     let pb = ProgressBar::new(total_size);
-    pb.set_style(
-        ProgressStyle::default_bar()
-            .template(&format!(
-                "=> {app} {bar}",
-                app = app_name,
-                bar = "{wide_msg}[{bar:60.green/blue}] {percent:>3}% {total_bytes:>10}"
-            ))
-            .progress_chars("#>-"),
-    );
+    pb.set_style(bar_style(app_name));
 
     let file = Path::new(file_path);
 
@@ -78,6 +96,133 @@ pub async fn download_http(
     Ok(())
 }
 
+/// Fetch `downloads` with at most `limit` of them running at once, each with
+/// its own bar above one for the bytes of all of them. A failed download
+/// does not stop the others; the errors are returned together.
+pub async fn download_all(downloads: Vec<Download>, limit: usize) -> Result<(), Vec<String>> {
+    let client = match client() {
+        Ok(c) => c,
+        Err(e) => return Err(vec![e.to_string()]),
+    };
+    let mp = MultiProgress::new();
+    let mut jobs: Vec<(Download, ProgressBar)> = Vec::new();
+    for download in downloads.into_iter() {
+        let pb = mp.add(ProgressBar::new(download.size.unwrap_or(0)));
+        pb.set_style(bar_style(&download.name));
+        pb.set_message("waiting ");
+        jobs.push((download, pb));
+    }
+    let bars: Vec<ProgressBar> = jobs.iter().map(|(_, pb)| pb.clone()).collect();
+    let total = mp.add(ProgressBar::new(
+        jobs.iter().filter_map(|(d, _)| d.size).sum(),
+    ));
+    total.set_style(bar_style("total"));
+
+    let workers = limit.max(1).min(jobs.len());
+
+    jobs.reverse();
+    let queue = Arc::new(Mutex::new(jobs));
+    let errors: Arc<Mutex<Vec<String>>> = Arc::new(Mutex::new(Vec::new()));
+    let mut handles = Vec::new();
+    for _ in 0..workers {
+        let client = client.clone();
+        let queue = queue.clone();
+        let errors = errors.clone();
+        let total = total.clone();
+        handles.push(tokio::spawn(async move {
+            loop {
+                let job = queue.lock().unwrap().pop();
+                let (download, pb) = match job {
+                    Some(job) => job,
+                    None => break,
+                };
+                pb.set_message("");
+                if let Err(e) = fetch(&client, &download, &pb, &total).await {
+                    pb.abandon_with_message("failed ");
+                    errors
+                        .lock()
+                        .unwrap()
+                        .push(format!("{}: {}", download.name, e));
+                }
+            }
+        }));
+    }
+    for handle in handles.into_iter() {
+        if let Err(e) = handle.await {
+            errors.lock().unwrap().push(e.to_string());
+        }
+    }
+    // bars a panicked worker left behind would still show as running
+    for pb in bars.iter().filter(|pb| !pb.is_finished()) {
+        pb.abandon();
+    }
+    total.finish();
+
+    let errors = errors.lock().unwrap().clone();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+// Download one file into place, resuming a partial one.
+async fn fetch(
+    client: &Client,
+    download: &Download,
+    pb: &ProgressBar,
+    total: &ProgressBar,
+) -> Result<(), anyhow::Error> {
+    let size = match download.size {
+        Some(size) => size,
+        None => {
+            let size = content_length(client, &download.address).await?;
+            total.inc_length(size);
+            size
+        }
+    };
+    pb.set_length(size);
+
+    let mut start = match fs::metadata(&download.file).await {
+        Ok(meta) if meta.len() < size => meta.len(),
+        _ => 0,
+    };
+    let mut request = client.get(&download.address);
+    if start > 0 {
+        request = request.header(header::RANGE, format!("bytes={}-", start));
+    }
+    let mut source = request.send().await?;
+    if !source.status().is_success() {
+        return Err(anyhow!(
+            "Couldn't download URL: {}. Error: {:?}",
+            download.address,
+            source.status(),
+        ));
+    }
+    // the server may not do ranges and send the whole file
+    if source.status() != StatusCode::PARTIAL_CONTENT {
+        start = 0;
+    }
+    pb.inc(start);
+    total.inc(start);
+
+    let mut dest = fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(start > 0)
+        .truncate(start == 0)
+        .open(&download.file)
+        .await?;
+    while let Some(chunk) = source.chunk().await? {
+        dest.write_all(&chunk).await?;
+        pb.inc(chunk.len() as u64);
+        total.inc(chunk.len() as u64);
+    }
+    dest.flush().await?;
+    pb.finish();
+    Ok(())
+}
+
 pub fn download_git(url: &str, clone_to: &str) {
     match Repository::clone(url, clone_to) {
         Ok(repo) => repo,
@@ -0,0 +1,38 @@
+use std::{
+    fs::{set_permissions, Permissions},
+    io::{ErrorKind, Result},
+    os::unix::fs::{lchown, MetadataExt, PermissionsExt},
+    path::Path,
+};
+
+/// Give `path` an owner and mode. The owner goes first since changing it
+/// clears setuid and setgid bits. Unless pi runs as root, files keep the
+/// owner of whoever unpacked them.
+pub fn set_metadata(
+    path: &Path,
+    uid: Option<u32>,
+    gid: Option<u32>,
+    mode: Option<u32>,
+) -> Result<()> {
+    if uid.is_some() || gid.is_some() {
+        match lchown(path, uid, gid) {
+            Err(e) if e.kind() == ErrorKind::PermissionDenied => {}
+            result => result?,
+        }
+    }
+    if let Some(mode) = mode {
+        set_permissions(path, Permissions::from_mode(mode))?;
+    }
+    Ok(())
+}
+
+/// Give `to` the owner and mode of `from`.
+pub fn copy_metadata(from: &Path, to: &Path) -> Result<()> {
+    let meta = from.symlink_metadata()?;
+    let mode = if meta.file_type().is_symlink() {
+        None
+    } else {
+        Some(meta.mode() & 0o7777)
+    };
+    set_metadata(to, Some(meta.uid()), Some(meta.gid()), mode)
+}
@@ -2,12 +2,16 @@ mod archive;
 mod compress;
 mod decompress;
 mod download;
+mod metadata;
+mod move_file;
 mod prepare;
 mod read_file;
 
 pub use archive::*;
 pub use compress::compress_zstd;
 pub use decompress::*;
-pub use download::{download_git, download_http};
+pub use download::{download_all, download_git, download_http, Download};
+pub use metadata::{copy_metadata, set_metadata};
+pub use move_file::move_path;
 pub use prepare::{prepare_base, prepare_bases};
 pub use read_file::read_to_vec_u8;
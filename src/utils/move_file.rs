@@ -0,0 +1,26 @@
+use super::copy_metadata;
+use std::{
+    fs::{copy, read_link, remove_file, rename},
+    io::Result,
+    os::unix::fs::symlink,
+    path::Path,
+};
+
+// EXDEV, the source and target are on different filesystems.
+const CROSS_DEVICE: i32 = 18;
+
+pub fn move_path(from: &Path, to: &Path) -> Result<()> {
+    match rename(from, to) {
+        Ok(_) => Ok(()),
+        Err(e) if e.raw_os_error() == Some(CROSS_DEVICE) => {
+            if from.symlink_metadata()?.file_type().is_symlink() {
+                symlink(read_link(from)?, to)?;
+            } else {
+                copy(from, to)?;
+            }
+            copy_metadata(from, to)?;
+            remove_file(from)
+        }
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,223 @@
+use crate::Metadata;
+use std::{cmp::Ordering, fmt};
+
+// A full package version `[epoch:]version[-release]`. The epoch wins over
+// everything else, then the upstream version, then the release.
+#[derive(Clone, Debug, Eq)]
+pub struct PkgVersion {
+    pub epoch: u32,
+    pub version: String,
+    pub release: Option<u32>,
+}
+
+impl PkgVersion {
+    /// Parse a version as typed by a user, e.g. `1:2.36-2`, `2.36` or
+    /// `1.2.2-rc1-1`. A missing release matches any release.
+    pub fn parse(text: &str) -> Self {
+        let (epoch, rest) = match text.find(':') {
+            Some(pos) if text[..pos].chars().all(|c| c.is_ascii_digit()) && pos > 0 => {
+                (text[..pos].parse().unwrap_or(0), &text[pos + 1..])
+            }
+            _ => (0, text),
+        };
+        let (version, release) = match rest.rfind('-') {
+            Some(pos) => match rest[pos + 1..].parse::<u32>() {
+                Ok(release) => (&rest[..pos], Some(release)),
+                Err(_) => (rest, None),
+            },
+            None => (rest, None),
+        };
+        Self {
+            epoch,
+            version: version.to_string(),
+            release,
+        }
+    }
+
+    pub fn of(meta: &Metadata) -> Self {
+        Self {
+            epoch: meta.epoch,
+            version: meta.version.clone(),
+            release: Some(meta.release),
+        }
+    }
+}
+
+impl Ord for PkgVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.epoch
+            .cmp(&other.epoch)
+            .then_with(|| vercmp(&self.version, &other.version))
+            .then_with(|| match (self.release, other.release) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                _ => Ordering::Equal,
+            })
+    }
+}
+
+impl PartialOrd for PkgVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for PkgVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl fmt::Display for PkgVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.epoch > 0 {
+            write!(f, "{}:", self.epoch)?;
+        }
+        write!(f, "{}", self.version)?;
+        if let Some(release) = self.release {
+            write!(f, "-{}", release)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare two upstream version strings the way rpm and pacman do. Both are
+/// split into runs of digits and runs of letters, anything else only
+/// separates them. Digit runs compare as numbers and beat letter runs, so
+/// `1.10 > 1.9`, `2.36 > 2.4a` and `1.0 > 1.0rc1` but `1.0.1 > 1.0`.
+pub fn vercmp(a: &str, b: &str) -> Ordering {
+    if a == b {
+        return Ordering::Equal;
+    }
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let (mut i, mut j) = (0, 0);
+    loop {
+        while i < a.len() && !a[i].is_ascii_alphanumeric() {
+            i += 1;
+        }
+        while j < b.len() && !b[j].is_ascii_alphanumeric() {
+            j += 1;
+        }
+        if i >= a.len() || j >= b.len() {
+            break;
+        }
+
+        let numeric = a[i].is_ascii_digit();
+        let same = |c: u8| {
+            if numeric {
+                c.is_ascii_digit()
+            } else {
+                c.is_ascii_alphabetic()
+            }
+        };
+        let start = (i, j);
+        while i < a.len() && same(a[i]) {
+            i += 1;
+        }
+        while j < b.len() && same(b[j]) {
+            j += 1;
+        }
+        let (x, y) = (&a[start.0..i], &b[start.1..j]);
+        // a number against letters, the number is newer
+        if y.is_empty() {
+            return if numeric {
+                Ordering::Greater
+            } else {
+                Ordering::Less
+            };
+        }
+
+        let order = if numeric {
+            let x = trim_zeros(x);
+            let y = trim_zeros(y);
+            x.len().cmp(&y.len()).then_with(|| x.cmp(y))
+        } else {
+            x.cmp(y)
+        };
+        if order != Ordering::Equal {
+            return order;
+        }
+    }
+
+    // whatever is left decides, but trailing letters never beat nothing
+    let (rest_a, rest_b) = (&a[i.min(a.len())..], &b[j.min(b.len())..]);
+    match (rest_a.first(), rest_b.first()) {
+        (None, None) => Ordering::Equal,
+        (None, Some(c)) if !c.is_ascii_alphabetic() => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(c), _) if c.is_ascii_alphabetic() => Ordering::Less,
+        (Some(_), _) => Ordering::Greater,
+    }
+}
+
+fn trim_zeros(digits: &[u8]) -> &[u8] {
+    let start = digits
+        .iter()
+        .position(|c| *c != b'0')
+        .unwrap_or(digits.len());
+    &digits[start..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares_versions() {
+        let table = [
+            ("1.0", "1.0", Ordering::Equal),
+            ("1.0rc1", "1.0", Ordering::Less),
+            ("1.0", "1.0.1", Ordering::Less),
+            ("1.0a", "1.0.1", Ordering::Less),
+            ("1.0a", "1.0", Ordering::Less),
+            ("1.9", "1.10", Ordering::Less),
+            ("2.4a", "2.36", Ordering::Less),
+            ("1.01", "1.1", Ordering::Equal),
+            ("1.0-rc1", "1.0.rc1", Ordering::Equal),
+            ("alpha", "beta", Ordering::Less),
+        ];
+        for (a, b, order) in table.iter() {
+            assert_eq!(vercmp(a, b), *order, "{} vs {}", a, b);
+            assert_eq!(vercmp(b, a), order.reverse(), "{} vs {}", b, a);
+        }
+    }
+
+    #[test]
+    fn compares_package_versions() {
+        let table = [
+            // the epoch goes first
+            ("1:1.0-1", "2.0-1", Ordering::Greater),
+            ("1:1.0-1", "1:1.0-1", Ordering::Equal),
+            ("0:1.0-1", "1.0-1", Ordering::Equal),
+            // then the version, then the release
+            ("1.0-2", "1.0.1-1", Ordering::Less),
+            ("1.0-2", "1.0-10", Ordering::Less),
+            ("1.0rc1-5", "1.0-1", Ordering::Less),
+            // no release matches any
+            ("1.0", "1.0-7", Ordering::Equal),
+        ];
+        for (a, b, order) in table.iter() {
+            let (x, y) = (PkgVersion::parse(a), PkgVersion::parse(b));
+            assert_eq!(x.cmp(&y), *order, "{} vs {}", a, b);
+            assert_eq!(y.cmp(&x), order.reverse(), "{} vs {}", b, a);
+        }
+    }
+
+    #[test]
+    fn parses_versions() {
+        let table = [
+            ("1:2.36-2", 1, "2.36", Some(2)),
+            ("2.36", 0, "2.36", None),
+            ("1.2.2-rc1-1", 0, "1.2.2-rc1", Some(1)),
+            ("1.2.2-rc1", 0, "1.2.2-rc1", None),
+            ("x:1.0", 0, "x:1.0", None),
+        ];
+        for (text, epoch, version, release) in table.iter() {
+            let v = PkgVersion::parse(text);
+            assert_eq!(
+                (v.epoch, v.version.as_str(), v.release),
+                (*epoch, *version, *release)
+            );
+            assert_eq!(v.to_string(), text.trim_start_matches("0:"), "{}", text);
+        }
+    }
+}